@@ -0,0 +1,485 @@
+//! Fixture data derived from the index of a stock only, so every run serves the same.
+use rpc::api::{BalanceSheetItem, CashFlow, CashFlowStatementItem, DebtDecapitalStructure, GuideLineResp, IncomeAnalysisResp,
+               IncomeStatementItem, OperationAbility, Profitability, Quote, ShareIndex, StockIssueResp, StockResp, StockStatus,
+               TradingHistoryItem, TradingHistoryType};
+
+pub const STOCK_COUNT: usize = 96;
+/// (year, month, day) of the first and last bars
+pub const HISTORY_START: (i64, u32, u32) = (2019, 1, 2);
+pub const HISTORY_END: (i64, u32, u32) = (2023, 6, 30);
+/// latest annual report
+pub const REPORT_YEAR: i64 = 2022;
+/// ticks after which quotes stop moving
+const QUOTE_TICKS: u64 = 4800;
+
+const INDUSTRIES: [&str; 12] = ["银行", "证券", "保险", "白酒", "医药", "半导体", "电池", "汽车", "软件", "电力", "钢铁", "地产"];
+const PREFIXES: [&str; 8] = ["华", "中", "东", "海", "新", "金", "长", "安"];
+const SUFFIXES: [&str; 6] = ["信", "兴", "泰", "达", "丰", "盛"];
+
+/// Linear congruential generator, fixtures must not depend on a random source.
+pub struct Lcg(u64);
+
+impl Lcg {
+    pub fn new(seed: u64) -> Self {
+        let mut lcg = Self(seed);
+        lcg.uniform();
+        lcg
+    }
+    /// Uniform in `[0, 1)`.
+    pub fn uniform(&mut self) -> f64 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Days since 1970-01-01 of a date.
+pub fn days_from_civil((y, m, d): (i64, u32, u32)) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (m as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + d as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    (if m <= 2 { yoe + era * 400 + 1 } else { yoe + era * 400 }, m, d)
+}
+
+pub fn format_date(days: i64) -> String {
+    let (y, m, d) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+/// Days of `YYYY-MM-DD`.
+pub fn parse_date(text: &str) -> Option<i64> {
+    let mut parts = text.splitn(3, '-');
+    let y = parts.next()?.parse().ok()?;
+    let m = parts.next()?.parse().ok()?;
+    let d = parts.next()?.parse().ok()?;
+    Some(days_from_civil((y, m, d)))
+}
+
+fn is_trading_day(days: i64) -> bool {
+    // 1970-01-01 was a Thursday
+    (days + 3).rem_euclid(7) < 5
+}
+
+fn next_trading_day(days: i64) -> i64 {
+    (days + 1..).find(|x| is_trading_day(*x)).unwrap()
+}
+
+fn amount(value: f64) -> String {
+    format!("{:.2}", value)
+}
+
+pub fn stocks() -> Vec<StockResp> {
+    (0..STOCK_COUNT).map(stock).collect()
+}
+
+pub fn stock(i: usize) -> StockResp {
+    let (exchange, board, code) = match i % 6 {
+        0 | 1 => ("SH", "main", 600000 + i),
+        2 => ("SH", "star", 688000 + i),
+        3 => ("SZ", "main", 1 + i),
+        4 => ("SZ", "chinext", 300000 + i),
+        _ => ("BJ", "bse", 830000 + i),
+    };
+    let code = format!("{:06}", code);
+    let industry = INDUSTRIES[i % INDUSTRIES.len()];
+    let status = if i % 31 == 30 {
+        StockStatus::Delisted
+    } else if i % 23 == 7 {
+        StockStatus::St
+    } else {
+        StockStatus::Normal
+    };
+    let name = format!("{}{}{}{}", if status == StockStatus::St { "ST" } else { "" },
+                       PREFIXES[i % PREFIXES.len()], SUFFIXES[(i / 8 + i / 48) % SUFFIXES.len()], industry);
+    StockResp {
+        symbol: format!("{}{}", exchange.to_lowercase(), code),
+        code,
+        name,
+        id: (i + 1).to_string(),
+        exchange: exchange.to_string(),
+        board: board.to_string(),
+        industry: industry.to_string(),
+        list_date: format_date(list_day(i)),
+        status: status as i32,
+    }
+}
+
+fn list_day(i: usize) -> i64 {
+    let day = days_from_civil((1998, 1, 5)) + i as i64 * 97;
+    if is_trading_day(day) { day } else { next_trading_day(day) }
+}
+
+/// Index of the stock with `text` as code or symbol.
+pub fn find_stock(text: &str) -> Option<usize> {
+    (0..STOCK_COUNT).find(|i| {
+        let stock = stock(*i);
+        stock.code == text || stock.symbol == text
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Bar {
+    pub day: i64,
+    pub open: f64,
+    pub close: f64,
+    pub high: f64,
+    pub low: f64,
+    pub volume: u64,
+}
+
+impl Bar {
+    pub fn item(&self) -> TradingHistoryItem {
+        TradingHistoryItem {
+            date: format_date(self.day),
+            open: amount(self.open),
+            close: amount(self.close),
+            high: amount(self.high),
+            low: amount(self.low),
+            volume: self.volume.to_string(),
+        }
+    }
+}
+
+fn last_day(i: usize) -> i64 {
+    let end = days_from_civil(HISTORY_END);
+    if stock(i).status() == StockStatus::Delisted { end - 365 } else { end }
+}
+
+pub fn daily_bars(i: usize) -> Vec<Bar> {
+    let mut rng = Lcg::new(i as u64 + 1);
+    let mut close = 5.0 + (i % 40) as f64 * 1.5;
+    let start = days_from_civil(HISTORY_START).max(list_day(i));
+    (start..=last_day(i)).filter(|x| is_trading_day(*x)).map(|day| {
+        let open = close * (1.0 + (rng.uniform() - 0.5) * 0.02);
+        close = (open * (1.0003 + (rng.uniform() - 0.5) * 0.06)).max(0.5);
+        Bar {
+            day,
+            open,
+            close,
+            high: open.max(close) * (1.0 + rng.uniform() * 0.02),
+            low: open.min(close) * (1.0 - rng.uniform() * 0.02),
+            volume: 100000 + (rng.uniform() * 900000.0) as u64,
+        }
+    }).collect()
+}
+
+/// Bars of the timeframe, weekly and monthly ones dated by their last trading day.
+pub fn bars(i: usize, typ: TradingHistoryType) -> Vec<Bar> {
+    let daily = daily_bars(i);
+    let period = |day: i64| match typ {
+        TradingHistoryType::Daily => day,
+        TradingHistoryType::Week => (day + 3).div_euclid(7),
+        TradingHistoryType::Month => {
+            let (y, m, _) = civil_from_days(day);
+            y * 12 + m as i64
+        }
+    };
+    let mut result: Vec<(i64, Bar)> = vec![];
+    for bar in daily {
+        let p = period(bar.day);
+        if let Some((last_p, last)) = result.last_mut() {
+            if *last_p == p {
+                last.day = bar.day;
+                last.close = bar.close;
+                last.high = last.high.max(bar.high);
+                last.low = last.low.min(bar.low);
+                last.volume += bar.volume;
+                continue;
+            }
+        }
+        result.push((p, bar));
+    }
+    result.into_iter().map(|(_, bar)| bar).collect()
+}
+
+/// Quote of the trading day after history at `tick`, `None` for delisted stocks.
+pub fn quote(i: usize, tick: u64, timestamp: i64) -> Option<Quote> {
+    let stock = stock(i);
+    if stock.status() == StockStatus::Delisted {
+        return None;
+    }
+    let last = *daily_bars(i).last()?;
+    let pre_close = last.close;
+    let price_at = |k: u64| pre_close * (1.0 + 0.03 * ((k as f64 * 0.37 + i as f64).sin() * 0.6 + (k as f64 * 0.11).sin() * 0.4));
+    let tick = tick.min(QUOTE_TICKS);
+    let prices: Vec<f64> = (0..=tick).map(price_at).collect();
+    Some(Quote {
+        symbol: stock.symbol,
+        date: format_date(next_trading_day(last.day)),
+        open: prices[0] as f32,
+        high: prices.iter().cloned().fold(f64::MIN, f64::max) as f32,
+        low: prices.iter().cloned().fold(f64::MAX, f64::min) as f32,
+        price: prices[tick as usize] as f32,
+        pre_close: pre_close as f32,
+        volume: 1000 * (tick + 1) * (1 + i as u64 % 5),
+        timestamp,
+    })
+}
+
+/// Shares in 10k.
+fn capital(i: usize) -> f64 {
+    let mut rng = Lcg::new(1000 + i as u64);
+    (2000.0 + rng.uniform() * 40000.0) * 4.0
+}
+
+pub fn stock_issue(i: usize) -> StockIssueResp {
+    let stock = stock(i);
+    let mut rng = Lcg::new(2000 + i as u64);
+    let capital = capital(i);
+    let issue_volume = capital / 4.0;
+    let issue_price = 3.0 + rng.uniform() * 30.0;
+    let fundraising = issue_price * issue_volume;
+    let issue_cost = fundraising * (0.04 + rng.uniform() * 0.04);
+    let market = match stock.exchange.as_str() {
+        "SH" => "上海证券交易所",
+        "SZ" => "深圳证券交易所",
+        _ => "北京证券交易所",
+    };
+    let list_day = list_day(i);
+    StockIssueResp {
+        market: market.to_string(),
+        consignee: "中国证券登记结算有限责任公司".to_string(),
+        underwriting: "余额包销".to_string(),
+        sponsor: format!("{}{}证券股份有限公司", PREFIXES[(i + 3) % PREFIXES.len()], SUFFIXES[i % SUFFIXES.len()]),
+        issue_price: amount(issue_price),
+        issue_mode: "网下询价配售和网上定价发行相结合".to_string(),
+        issue_pe: amount(15.0 + rng.uniform() * 40.0),
+        pre_capital: amount(capital - issue_volume),
+        capital: amount(capital),
+        issue_volume: amount(issue_volume),
+        expected_fundraising: amount(fundraising * 0.9),
+        fundraising: amount(fundraising),
+        issue_cost: amount(issue_cost),
+        net_amount_raised: amount(fundraising - issue_cost),
+        underwriting_fee: amount(issue_cost * 0.7),
+        announcement_date: format_date(list_day - 14),
+        launch_date: format_date(list_day),
+    }
+}
+
+/// Revenue in yuan of a year.
+fn revenue(i: usize, year: i64) -> f64 {
+    let base = (20.0 + (i % 17) as f64 * 13.0) * 1e8;
+    let growth = 1.0 + ((i % 9) as f64 - 2.0) * 0.03;
+    let mut rng = Lcg::new((i as u64) << 16 | year as u64);
+    base * growth.powi((year - 2015) as i32) * (0.95 + rng.uniform() * 0.1)
+}
+
+pub fn income_analysis(i: usize, years: u32) -> IncomeAnalysisResp {
+    let years = if years == 0 { 3 } else { years };
+    let years: Vec<i64> = (0..years as i64).map(|x| REPORT_YEAR - x).collect();
+    let incomes: Vec<f32> = years.iter().map(|x| revenue(i, *x) as f32).collect();
+    IncomeAnalysisResp {
+        ave: incomes.iter().sum::<f32>() / incomes.len() as f32,
+        incomes,
+        years: years.iter().map(|x| x.to_string()).collect(),
+    }
+}
+
+/// Quarterly reporting periods up to the latest, oldest first.
+fn periods() -> Vec<(i64, u32)> {
+    let (end_year, end_month, _) = HISTORY_END;
+    (2018..=end_year).flat_map(|y| (1..=4).map(move |q| (y, q)))
+        .filter(|(y, q)| *y < end_year || q * 3 < end_month)
+        .collect()
+}
+
+fn period_date((year, quarter): (i64, u32)) -> String {
+    let (month, day) = [(3, 31), (6, 30), (9, 30), (12, 31)][quarter as usize - 1];
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+pub fn guide_line(i: usize, year: &str) -> GuideLineResp {
+    let year: i64 = year.parse().unwrap_or(REPORT_YEAR);
+    let mut resp = GuideLineResp::default();
+    for period in periods().into_iter().filter(|(y, _)| *y == year) {
+        let date = period_date(period);
+        let mut rng = Lcg::new((i as u64) << 20 | (period.0 as u64) << 4 | period.1 as u64);
+        let roe = 5.0 + rng.uniform() * 20.0;
+        let eps = revenue(i, year) * 0.12 * period.1 as f64 / 4.0 / (capital(i) * 1e4);
+        resp.share_index.push(ShareIndex {
+            date: date.to_string(),
+            diluted_eps: amount(eps),
+            bps: amount(eps * 100.0 / roe * 4.0 / period.1 as f64),
+            ocfps: amount(eps * (0.8 + rng.uniform() * 0.6)),
+            total_shares: amount(capital(i) * 1e4),
+            // dividends are paid out of annual profits
+            dps: amount(if period.1 == 4 { eps * 0.3 } else { 0.0 }),
+            ..Default::default()
+        });
+        resp.profitability.push(Profitability {
+            date: date.to_string(),
+            roe: amount(roe * period.1 as f64 / 4.0),
+            roa: amount(roe * 0.45 * period.1 as f64 / 4.0),
+            gp: amount(20.0 + rng.uniform() * 40.0),
+            nmp: amount(8.0 + rng.uniform() * 10.0),
+            ..Default::default()
+        });
+        resp.operation_ability.push(OperationAbility {
+            date: date.to_string(),
+            art: amount(4.0 + rng.uniform() * 8.0),
+            tato: amount(0.3 + rng.uniform() * 0.8),
+            ..Default::default()
+        });
+        resp.debt_decapital_structure.push(DebtDecapitalStructure {
+            date: date.to_string(),
+            cr: amount(1.0 + rng.uniform() * 2.0),
+            qr: amount(0.6 + rng.uniform() * 1.4),
+            ar: amount(30.0 + rng.uniform() * 40.0),
+            ..Default::default()
+        });
+        resp.cash_flow.push(CashFlow {
+            date,
+            nocftsr: amount(0.05 + rng.uniform() * 0.2),
+            ..Default::default()
+        });
+    }
+    resp
+}
+
+/// Year-to-date revenue of a period and noise shared by its statements.
+fn period_revenue(i: usize, period: (i64, u32)) -> (f64, Lcg) {
+    let rng = Lcg::new((i as u64) << 24 | (period.0 as u64) << 4 | period.1 as u64);
+    (revenue(i, period.0) * period.1 as f64 / 4.0, rng)
+}
+
+/// Newest first, at most `periods` of them unless 0.
+fn recent_periods(periods: u32) -> Vec<(i64, u32)> {
+    let all = self::periods().into_iter().rev();
+    if periods == 0 { all.collect() } else { all.take(periods as usize).collect() }
+}
+
+pub fn income_statement(i: usize, periods: u32) -> Vec<IncomeStatementItem> {
+    recent_periods(periods).into_iter().map(|period| {
+        let (r, mut rng) = period_revenue(i, period);
+        let cost = r * (0.62 + rng.uniform() * 0.16);
+        let expenses = [0.01, 0.05, 0.04, 0.03, 0.01].map(|x| r * x);
+        let operating_profit = r - cost - expenses.iter().sum::<f64>();
+        let total_profit = operating_profit + r * 0.003;
+        let net_profit = total_profit * 0.75;
+        IncomeStatementItem {
+            date: period_date(period),
+            revenue: amount(r),
+            operating_cost: amount(cost),
+            taxes_and_surcharges: amount(expenses[0]),
+            selling_expenses: amount(expenses[1]),
+            admin_expenses: amount(expenses[2]),
+            rd_expenses: amount(expenses[3]),
+            financial_expenses: amount(expenses[4]),
+            operating_profit: amount(operating_profit),
+            non_operating_income: amount(r * 0.005),
+            non_operating_expenses: amount(r * 0.002),
+            total_profit: amount(total_profit),
+            income_tax: amount(total_profit * 0.25),
+            net_profit: amount(net_profit),
+            net_profit_parent: amount(net_profit * 0.95),
+            basic_eps: format!("{:.4}", net_profit * 0.95 / (capital(i) * 1e4)),
+        }
+    }).collect()
+}
+
+pub fn balance_sheet(i: usize, periods: u32) -> Vec<BalanceSheetItem> {
+    recent_periods(periods).into_iter().map(|period| {
+        let a = revenue(i, period.0) * 2.2;
+        BalanceSheetItem {
+            date: period_date(period),
+            cash: amount(a * 0.15),
+            notes_receivable: amount(a * 0.02),
+            accounts_receivable: amount(a * 0.1),
+            prepayments: amount(a * 0.02),
+            inventories: amount(a * 0.11),
+            current_assets: amount(a * 0.4),
+            long_term_investments: amount(a * 0.12),
+            fixed_assets: amount(a * 0.3),
+            construction_in_progress: amount(a * 0.06),
+            intangible_assets: amount(a * 0.09),
+            goodwill: amount(a * 0.03),
+            non_current_assets: amount(a * 0.6),
+            total_assets: amount(a),
+            short_term_loans: amount(a * 0.08),
+            notes_payable: amount(a * 0.03),
+            accounts_payable: amount(a * 0.12),
+            advance_receipts: amount(a * 0.04),
+            current_liabilities: amount(a * 0.3),
+            long_term_loans: amount(a * 0.1),
+            bonds_payable: amount(a * 0.05),
+            non_current_liabilities: amount(a * 0.15),
+            total_liabilities: amount(a * 0.45),
+            paid_in_capital: amount(capital(i) * 1e4),
+            capital_reserve: amount(a * 0.25 - capital(i) * 1e4),
+            retained_earnings: amount(a * 0.3),
+            total_equity: amount(a * 0.55),
+        }
+    }).collect()
+}
+
+pub fn cash_flow_statement(i: usize, periods: u32) -> Vec<CashFlowStatementItem> {
+    recent_periods(periods).into_iter().map(|period| {
+        let (r, mut rng) = period_revenue(i, period);
+        let operating_inflow = r * (1.1 + rng.uniform() * 0.1);
+        let operating_outflow = r * 0.98;
+        let capital_expenditure = r * 0.08;
+        let dividends_paid = r * 0.03;
+        let net_operating = operating_inflow - operating_outflow;
+        let net_investing = r * 0.02 - capital_expenditure - r * 0.02;
+        let net_financing = r * 0.06 - dividends_paid - r * 0.06;
+        CashFlowStatementItem {
+            date: period_date(period),
+            cash_from_sales: amount(operating_inflow * 0.95),
+            operating_inflow: amount(operating_inflow),
+            operating_outflow: amount(operating_outflow),
+            net_operating_cash_flow: amount(net_operating),
+            investing_inflow: amount(r * 0.02),
+            capital_expenditure: amount(capital_expenditure),
+            investing_outflow: amount(capital_expenditure + r * 0.02),
+            net_investing_cash_flow: amount(net_investing),
+            financing_inflow: amount(r * 0.06),
+            dividends_paid: amount(dividends_paid),
+            financing_outflow: amount(dividends_paid + r * 0.06),
+            net_financing_cash_flow: amount(net_financing),
+            net_cash_flow: amount(net_operating + net_investing + net_financing),
+            ending_cash: amount(revenue(i, period.0) * 2.2 * 0.15),
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use rpc::api::TradingHistoryType;
+    use crate::fixtures::{bars, civil_from_days, days_from_civil, parse_date, stocks, STOCK_COUNT};
+
+    #[test]
+    fn dates() {
+        assert_eq!(days_from_civil((1970, 1, 1)), 0);
+        assert_eq!(civil_from_days(days_from_civil((2024, 2, 29))), (2024, 2, 29));
+        assert_eq!(parse_date("2023-06-30"), Some(days_from_civil((2023, 6, 30))));
+    }
+
+    #[test]
+    fn deterministic() {
+        let mut names: Vec<String> = stocks().into_iter().map(|x| x.name).collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), STOCK_COUNT);
+        let daily = bars(3, TradingHistoryType::Daily);
+        assert_eq!(daily.len(), bars(3, TradingHistoryType::Daily).len());
+        let weekly = bars(3, TradingHistoryType::Week);
+        assert!(weekly.len() < daily.len());
+        assert_eq!(weekly.last().unwrap().day, daily.last().unwrap().day);
+        assert_eq!(weekly.iter().map(|x| x.volume).sum::<u64>(), daily.iter().map(|x| x.volume).sum::<u64>());
+    }
+}
@@ -0,0 +1,84 @@
+//! In-process stand-in for the backend, implementing `ApiRpc` and `Register` over deterministic
+//! fixtures. Start it with [`spawn`] from tests, or run the binary to develop the app without
+//! the real server.
+#![cfg(not(target_arch = "wasm32"))]
+#![warn(clippy::all, rust_2018_idioms)]
+// handlers answer with `tonic::Status`, helpers share it
+#![allow(clippy::result_large_err)]
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+use rpc::api::api_rpc_server::ApiRpcServer;
+use rpc::api::register_server::RegisterServer;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::Code;
+use tonic::transport::Server;
+use tracing::info;
+
+pub mod fixtures;
+pub mod server;
+
+pub use server::{MockApi, MockRegister, Users};
+
+/// Every optional feature the client knows, see `FEATURE_*` there.
+pub const ALL_FEATURES: [&str; 8] = [
+    "predict", "stock_issue", "guide_line", "income_analysis", "statements",
+    "quotes", "stock_search", "stock_overview",
+];
+
+#[derive(Debug, Clone)]
+pub struct MockConfig {
+    /// delay before answering every call
+    pub latency: Duration,
+    /// fail every n-th call with `Code::Unavailable`, never when 0
+    pub fail_every: u32,
+    /// rpcs always failing with the code, by method name like `stock_issue`
+    pub failing: HashMap<String, Code>,
+    /// advertised in `ServerInfo`
+    pub features: Vec<String>,
+    /// interval between quotes of a symbol
+    pub quote_interval: Duration,
+}
+
+impl Default for MockConfig {
+    fn default() -> Self {
+        Self {
+            latency: Duration::ZERO,
+            fail_every: 0,
+            failing: HashMap::new(),
+            features: ALL_FEATURES.iter().map(|x| x.to_string()).collect(),
+            quote_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Serve on `addr` until the process exits, answering grpc-web as well for the web build.
+pub async fn serve(addr: SocketAddr, config: MockConfig) -> Result<(), tonic::transport::Error> {
+    info!("mock server listening on {}", addr);
+    router(config).serve(addr).await
+}
+
+/// Serve on a free local port in background, returns its address.
+pub async fn spawn(config: MockConfig) -> std::io::Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let router = router(config);
+    tokio::spawn(async move {
+        if let Err(e) = router.serve_with_incoming(TcpListenerStream::new(listener)).await {
+            tracing::error!("mock server stopped: {}", e);
+        }
+    });
+    Ok(addr)
+}
+
+fn router(config: MockConfig) -> tonic::transport::server::Router {
+    let users = Users::default();
+    let api = MockApi::new(config.clone(), users.clone());
+    let register = MockRegister::new(config, users);
+    Server::builder()
+        .accept_http1(true)
+        .add_service(tonic_web::enable(ApiRpcServer::new(api)))
+        .add_service(tonic_web::enable(RegisterServer::new(register)))
+}
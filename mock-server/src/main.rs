@@ -0,0 +1,48 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+use mock_server::MockConfig;
+use rpc::API_PORT;
+use tonic::Code;
+
+const USAGE: &str = "usage: mock_server [--addr IP:PORT] [--latency-ms N] [--fail-every N] [--fail METHOD=CODE]... \
+[--features A,B,...] [--quote-interval-ms N]";
+
+fn parse_args() -> Result<(SocketAddr, MockConfig), String> {
+    let mut addr: SocketAddr = ([0, 0, 0, 0], API_PORT).into();
+    let mut config = MockConfig::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("missing value of {}", arg));
+        let number = |text: String| text.parse::<u64>().map_err(|e| format!("{}: {}", text, e));
+        match arg.as_str() {
+            "--addr" => addr = value()?.parse().map_err(|e| format!("{}", e))?,
+            "--latency-ms" => config.latency = Duration::from_millis(number(value()?)?),
+            "--fail-every" => config.fail_every = number(value()?)? as u32,
+            "--fail" => {
+                let text = value()?;
+                let (method, code) = text.split_once('=').ok_or_else(|| format!("expect METHOD=CODE: {}", text))?;
+                config.failing.insert(method.to_string(), Code::from_i32(number(code.to_string())? as i32));
+            }
+            "--features" => config.features = value()?.split(',').filter(|x| !x.is_empty()).map(|x| x.to_string()).collect(),
+            "--quote-interval-ms" => config.quote_interval = Duration::from_millis(number(value()?)?),
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+    Ok((addr, config))
+}
+
+#[tokio::main]
+async fn main() {
+    tracing_subscriber::fmt::init();
+    let (addr, config) = match parse_args() {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    if let Err(e) = mock_server::serve(addr, config).await {
+        eprintln!("mock server failed: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,333 @@
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation};
+use rpc::api::api_rpc_server::ApiRpc;
+use rpc::api::register_server::Register;
+use rpc::api::{BalanceSheetResp, CashFlowStatementResp, GuideLineRequest, GuideLineResp, IncomeAnalysisRequest, IncomeAnalysisResp,
+               IncomeStatementResp, LoginRegisterRequest, LoginResp, PredictRequest, PredictResp, Quote, QuoteRequest, QuotesResp,
+               ReasonResp, ServerInfoResp, StatementRequest, StockIssueRequest, StockIssueResp, StockListRequest, StockListResp,
+               StockOverviewRequest, StockOverviewResp, TradingHistoryRequest, TradingHistoryResp, TradingHistoryType};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::Stream;
+use tonic::metadata::MetadataMap;
+use tonic::{Request, Response, Status};
+use tracing::{debug, info};
+use crate::fixtures;
+use crate::MockConfig;
+
+const TOKEN_SECRET: &[u8] = b"mock-server";
+const TOKEN_SECONDS: u64 = 3600;
+pub const PREDICT_MODEL: &str = "mock";
+
+/// Registered users and their passwords, shared by both services.
+pub type Users = Arc<Mutex<HashMap<String, String>>>;
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct Claims {
+    sub: String,
+    iat: u64,
+    exp: u64,
+}
+
+fn now_seconds() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_secs()).unwrap_or(0)
+}
+
+fn issue_token(username: &str) -> Result<String, Status> {
+    let now = now_seconds();
+    let claims = Claims { sub: username.to_string(), iat: now, exp: now + TOKEN_SECONDS };
+    jsonwebtoken::encode(&Header::default(), &claims, &EncodingKey::from_secret(TOKEN_SECRET))
+        .map_err(|e| Status::internal(e.to_string()))
+}
+
+/// Username of the bearer token in `metadata`.
+fn authorize(metadata: &MetadataMap) -> Result<String, Status> {
+    let token = metadata.get("authorization")
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.strip_prefix("Bearer "))
+        .ok_or_else(|| Status::unauthenticated("missing token"))?;
+    let data = jsonwebtoken::decode::<Claims>(token, &DecodingKey::from_secret(TOKEN_SECRET), &Validation::default())
+        .map_err(|e| Status::unauthenticated(e.to_string()))?;
+    Ok(data.claims.sub)
+}
+
+fn find_stock(text: &str) -> Result<usize, Status> {
+    fixtures::find_stock(text).ok_or_else(|| Status::not_found(format!("no stock {}", text)))
+}
+
+/// Applies latency and injected failures of [`MockConfig`].
+#[derive(Debug)]
+struct Gate {
+    config: MockConfig,
+    calls: AtomicU32,
+}
+
+impl Gate {
+    async fn enter(&self, method: &str) -> Result<(), Status> {
+        debug!("mock call: {}", method);
+        if !self.config.latency.is_zero() {
+            tokio::time::sleep(self.config.latency).await;
+        }
+        let n = self.calls.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Some(code) = self.config.failing.get(method) {
+            return Err(Status::new(*code, format!("injected failure of {}", method)));
+        }
+        if n.checked_rem(self.config.fail_every) == Some(0) {
+            return Err(Status::unavailable(format!("injected failure of call #{}", n)));
+        }
+        Ok(())
+    }
+}
+
+pub struct MockApi {
+    gate: Gate,
+    users: Users,
+    started: Instant,
+    started_at_ms: i64,
+}
+
+impl MockApi {
+    pub fn new(config: MockConfig, users: Users) -> Self {
+        Self {
+            gate: Gate { config, calls: AtomicU32::new(0) },
+            users,
+            started: Instant::now(),
+            started_at_ms: SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_millis() as i64).unwrap_or(0),
+        }
+    }
+    /// Quote ticks since start and the timestamp of the current one.
+    fn tick(started: Instant, started_at_ms: i64, interval: Duration) -> (u64, i64) {
+        let interval = interval.as_millis().max(1);
+        let tick = started.elapsed().as_millis() / interval;
+        (tick as u64, started_at_ms + (tick * interval) as i64)
+    }
+    fn trading_history(symbol: &str, typ: TradingHistoryType, since: &str) -> Result<TradingHistoryResp, Status> {
+        let i = find_stock(symbol)?;
+        let since = if since.is_empty() {
+            i64::MIN
+        } else {
+            fixtures::parse_date(since).ok_or_else(|| Status::invalid_argument(format!("invalid since {}", since)))?
+        };
+        let data = fixtures::bars(i, typ).into_iter().filter(|x| x.day >= since).map(|x| x.item()).collect();
+        Ok(TradingHistoryResp { data })
+    }
+}
+
+#[tonic::async_trait]
+impl ApiRpc for MockApi {
+    async fn ping(&self, _request: Request<()>) -> Result<Response<()>, Status> {
+        self.gate.enter("ping").await?;
+        Ok(Response::new(()))
+    }
+
+    async fn server_info(&self, _request: Request<()>) -> Result<Response<ServerInfoResp>, Status> {
+        self.gate.enter("server_info").await?;
+        Ok(Response::new(ServerInfoResp {
+            version: format!("mock-{}", env!("CARGO_PKG_VERSION")),
+            timeframes: [TradingHistoryType::Daily, TradingHistoryType::Week, TradingHistoryType::Month].map(|x| x as i32).to_vec(),
+            predict_models: vec![PREDICT_MODEL.to_string()],
+            features: self.gate.config.features.clone(),
+        }))
+    }
+
+    /// Any non-empty credentials log in, unless the user registered with another password.
+    async fn login(&self, request: Request<LoginRegisterRequest>) -> Result<Response<LoginResp>, Status> {
+        self.gate.enter("login").await?;
+        let request = request.into_inner();
+        let failed = |reason: &str| Response::new(LoginResp { err: true, token: "".to_string(), reason: reason.to_string() });
+        if request.username.is_empty() || request.password.is_empty() {
+            return Ok(failed("用户名或密码为空"));
+        }
+        let password = self.users.lock().unwrap().get(&request.username).cloned();
+        if matches!(password, Some(x) if x != request.password) {
+            return Ok(failed("密码错误"));
+        }
+        info!("login: {}", request.username);
+        Ok(Response::new(LoginResp { err: false, token: issue_token(&request.username)?, reason: "".to_string() }))
+    }
+
+    async fn refresh_token(&self, request: Request<()>) -> Result<Response<LoginResp>, Status> {
+        self.gate.enter("refresh_token").await?;
+        let username = authorize(request.metadata())?;
+        Ok(Response::new(LoginResp { err: false, token: issue_token(&username)?, reason: "".to_string() }))
+    }
+
+    async fn stock_list(&self, request: Request<StockListRequest>) -> Result<Response<StockListResp>, Status> {
+        self.gate.enter("stock_list").await?;
+        authorize(request.metadata())?;
+        let request = request.into_inner();
+        let query = request.query.trim().to_lowercase();
+        let data: Vec<_> = fixtures::stocks().into_iter().filter(|x| {
+            (query.is_empty() || x.code.contains(&query) || x.symbol.contains(&query) || x.name.to_lowercase().contains(&query))
+                && (request.market.is_empty() || x.exchange == request.market)
+                && (request.industry.is_empty() || x.industry.contains(&request.industry))
+                && (request.board.is_empty() || x.board == request.board)
+                && (request.statuses.is_empty() || request.statuses.contains(&x.status))
+        }).collect();
+        let total = data.len();
+        let offset: usize = if request.page_token.is_empty() {
+            0
+        } else {
+            request.page_token.parse().map_err(|_| Status::invalid_argument("invalid page token"))?
+        };
+        let page_size = if request.page_size == 0 { total } else { request.page_size as usize };
+        let next = offset + page_size;
+        Ok(Response::new(StockListResp {
+            data: data.into_iter().skip(offset).take(page_size).collect(),
+            next_page_token: if next < total { next.to_string() } else { "".to_string() },
+            total: total as u32,
+        }))
+    }
+
+    async fn trading_history(&self, request: Request<TradingHistoryRequest>) -> Result<Response<TradingHistoryResp>, Status> {
+        self.gate.enter("trading_history").await?;
+        authorize(request.metadata())?;
+        let request = request.into_inner();
+        Ok(Response::new(Self::trading_history(&request.symbol, request.typ(), &request.since)?))
+    }
+
+    /// Continues the recent trend of the series.
+    async fn predict_data(&self, request: Request<PredictRequest>) -> Result<Response<PredictResp>, Status> {
+        self.gate.enter("predict_data").await?;
+        authorize(request.metadata())?;
+        let request = request.into_inner();
+        if !request.model.is_empty() && request.model != PREDICT_MODEL {
+            return Err(Status::invalid_argument(format!("unknown model {}", request.model)));
+        }
+        let Some(last) = request.data.last().copied() else {
+            return Err(Status::invalid_argument("no data"));
+        };
+        let window = request.data.len().min(10);
+        let slope = (last - request.data[request.data.len() - window]) / window as f32 * 0.5;
+        Ok(Response::new(PredictResp { data: (1..=request.length).map(|k| last + slope * k as f32).collect() }))
+    }
+
+    async fn stock_issue(&self, request: Request<StockIssueRequest>) -> Result<Response<StockIssueResp>, Status> {
+        self.gate.enter("stock_issue").await?;
+        authorize(request.metadata())?;
+        Ok(Response::new(fixtures::stock_issue(find_stock(&request.get_ref().symbol)?)))
+    }
+
+    async fn guide_line(&self, request: Request<GuideLineRequest>) -> Result<Response<GuideLineResp>, Status> {
+        self.gate.enter("guide_line").await?;
+        authorize(request.metadata())?;
+        let request = request.into_inner();
+        Ok(Response::new(fixtures::guide_line(find_stock(&request.code)?, &request.year)))
+    }
+
+    async fn income_analysis(&self, request: Request<IncomeAnalysisRequest>) -> Result<Response<IncomeAnalysisResp>, Status> {
+        self.gate.enter("income_analysis").await?;
+        authorize(request.metadata())?;
+        let request = request.into_inner();
+        Ok(Response::new(fixtures::income_analysis(find_stock(&request.code)?, request.years)))
+    }
+
+    async fn income_statement(&self, request: Request<StatementRequest>) -> Result<Response<IncomeStatementResp>, Status> {
+        self.gate.enter("income_statement").await?;
+        authorize(request.metadata())?;
+        let request = request.into_inner();
+        Ok(Response::new(IncomeStatementResp { data: fixtures::income_statement(find_stock(&request.code)?, request.periods) }))
+    }
+
+    async fn balance_sheet(&self, request: Request<StatementRequest>) -> Result<Response<BalanceSheetResp>, Status> {
+        self.gate.enter("balance_sheet").await?;
+        authorize(request.metadata())?;
+        let request = request.into_inner();
+        Ok(Response::new(BalanceSheetResp { data: fixtures::balance_sheet(find_stock(&request.code)?, request.periods) }))
+    }
+
+    async fn cash_flow_statement(&self, request: Request<StatementRequest>) -> Result<Response<CashFlowStatementResp>, Status> {
+        self.gate.enter("cash_flow_statement").await?;
+        authorize(request.metadata())?;
+        let request = request.into_inner();
+        Ok(Response::new(CashFlowStatementResp { data: fixtures::cash_flow_statement(find_stock(&request.code)?, request.periods) }))
+    }
+
+    async fn stock_overview(&self, request: Request<StockOverviewRequest>) -> Result<Response<StockOverviewResp>, Status> {
+        self.gate.enter("stock_overview").await?;
+        authorize(request.metadata())?;
+        let request = request.into_inner();
+        let i = find_stock(&request.code)?;
+        let mut resp = StockOverviewResp::default();
+        match Self::trading_history(&request.symbol, request.typ(), &request.since) {
+            Ok(history) => resp.trading_history = Some(history),
+            Err(e) => resp.trading_history_error = e.message().to_string(),
+        }
+        if request.issue {
+            resp.issue = Some(fixtures::stock_issue(i));
+        }
+        if request.income_analysis {
+            resp.income_analysis = Some(fixtures::income_analysis(i, request.income_years));
+        }
+        Ok(Response::new(resp))
+    }
+
+    type SubscribeQuotesStream = Pin<Box<dyn Stream<Item = Result<Quote, Status>> + Send>>;
+
+    async fn subscribe_quotes(&self, request: Request<QuoteRequest>) -> Result<Response<Self::SubscribeQuotesStream>, Status> {
+        self.gate.enter("subscribe_quotes").await?;
+        authorize(request.metadata())?;
+        let stocks: Vec<usize> = request.into_inner().symbols.iter().filter_map(|x| fixtures::find_stock(x)).collect();
+        let (tx, rx) = tokio::sync::mpsc::channel(16);
+        let (started, started_at_ms, interval) = (self.started, self.started_at_ms, self.gate.config.quote_interval);
+        tokio::spawn(async move {
+            loop {
+                let (tick, timestamp) = Self::tick(started, started_at_ms, interval);
+                for quote in stocks.iter().filter_map(|i| fixtures::quote(*i, tick, timestamp)) {
+                    if tx.send(Ok(quote)).await.is_err() {
+                        return;
+                    }
+                }
+                tokio::select! {
+                    _ = tx.closed() => return,
+                    _ = tokio::time::sleep(interval) => {}
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+
+    async fn quotes(&self, request: Request<QuoteRequest>) -> Result<Response<QuotesResp>, Status> {
+        self.gate.enter("quotes").await?;
+        authorize(request.metadata())?;
+        let (tick, timestamp) = Self::tick(self.started, self.started_at_ms, self.gate.config.quote_interval);
+        let data = request.get_ref().symbols.iter()
+            .filter_map(|x| fixtures::find_stock(x))
+            .filter_map(|i| fixtures::quote(i, tick, timestamp))
+            .collect();
+        Ok(Response::new(QuotesResp { data }))
+    }
+}
+
+pub struct MockRegister {
+    gate: Gate,
+    users: Users,
+}
+
+impl MockRegister {
+    pub fn new(config: MockConfig, users: Users) -> Self {
+        Self { gate: Gate { config, calls: AtomicU32::new(0) }, users }
+    }
+}
+
+#[tonic::async_trait]
+impl Register for MockRegister {
+    async fn register(&self, request: Request<LoginRegisterRequest>) -> Result<Response<ReasonResp>, Status> {
+        self.gate.enter("register").await?;
+        let request = request.into_inner();
+        let reason = |err: bool, reason: &str| Response::new(ReasonResp { err, reason: reason.to_string() });
+        if request.username.is_empty() || request.password.is_empty() {
+            return Ok(reason(true, "用户名或密码为空"));
+        }
+        let mut users = self.users.lock().unwrap();
+        if users.contains_key(&request.username) {
+            return Ok(reason(true, "用户名已存在"));
+        }
+        info!("register: {}", request.username);
+        users.insert(request.username, request.password);
+        Ok(reason(false, "注册成功"))
+    }
+}
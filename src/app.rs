@@ -1,8 +1,9 @@
-use crate::constants::{AVAILABLE_API_HOSTS, REPAINT_AFTER_SECONDS};
+use crate::connection::ConnectionState;
+use crate::constants::REPAINT_AFTER_SECONDS;
 use crate::financial_analysis::FinancialAnalysis;
 use crate::run_mode::RunMode;
-use egui::{CentralPanel, ComboBox, Label, RichText, SidePanel, TopBottomPanel, Window};
-use regex::Regex;
+use crate::utils::{format_duration, now_seconds};
+use egui::{CentralPanel, ComboBox, RichText, SidePanel, TopBottomPanel, Window};
 
 impl eframe::App for FinancialAnalysis {
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
@@ -21,18 +22,23 @@ impl eframe::App for FinancialAnalysis {
             }
         }
 
-        if let Some(channel) = &self.channel {
+        if let Some(inbox) = &self.inbox {
             let mut messages = vec![];
-            while let Ok(rx) = channel.rx.try_recv() {
+            while let Ok(rx) = inbox.try_recv() {
                 messages.push(rx);
             }
             for rx in messages {
                 self.message_handler(rx);
             }
         }
-        if !self.stock_list_requesting && self.client.is_some() && self.stock_list.is_empty() && !self.token.is_empty() {
+        self.check_connection();
+        self.check_health();
+        self.check_token();
+        if !self.stock_list_requesting && self.stock_list_time.is_none() && self.stock_list_error.is_empty()
+            && ((self.connected() && !self.token.is_empty()) || self.offline) {
             self.load_stock_list();
         }
+        self.check_stock_search();
 
         TopBottomPanel::top("global_menu").show(ctx, |ui| {
             egui::menu::bar(ui, |ui| {
@@ -40,24 +46,33 @@ impl eframe::App for FinancialAnalysis {
                 ui.checkbox(&mut self.enable_debug_panel, "调试面板");
                 ui.add_enabled_ui(!self.token.is_empty(), |ui| {
                     if ui.button("退出登录").clicked() {
-                        self.token.clear();
-                        self.login_done = false;
+                        self.logout();
                     }
                 });
-                if ui.button("重新连接").clicked() {
-                    self.refresh_client();
+                if let Some(claims) = &self.claims {
+                    let text = match claims.exp {
+                        Some(exp) => format!("用户: {} (剩余 {})", claims.sub, format_duration(exp.saturating_sub(now_seconds()))),
+                        None => format!("用户: {}", claims.sub),
+                    };
+                    ui.label(text);
                 }
-                let last_api = self.api_host.clone();
-                ComboBox::new("api-select", "远程服务器地址")
-                    .selected_text(&self.api_host)
+                self.offline_ui(ui);
+                self.connection_status_ui(ui);
+                self.health.status_ui(ui);
+                let mut profile_index = self.profile_index;
+                ComboBox::new("profile-select", "服务器配置")
+                    .selected_text(format!("{} ({})", self.profile().name, self.profile().host))
                     .show_ui(ui, |ui| {
                         ui.style_mut().wrap = Some(false);
-                        for api in AVAILABLE_API_HOSTS {
-                            ui.selectable_value(&mut self.api_host, api.to_string(), api.to_string());
+                        for (i, profile) in self.profiles.iter().enumerate() {
+                            ui.selectable_value(&mut profile_index, i, format!("{} ({})", profile.name, profile.host));
                         }
                     });
-                if last_api != self.api_host {
-                    self.refresh_client();
+                if profile_index != self.profile_index {
+                    self.switch_profile(profile_index);
+                }
+                if ui.button("管理配置").clicked() {
+                    self.show_profiles_window = true;
                 }
             });
         });
@@ -66,46 +81,12 @@ impl eframe::App for FinancialAnalysis {
                 self.debug_panel(ui);
             });
         }
-        let enabled = self.login_done && !self.token.is_empty();
+        let enabled = (self.login_done && !self.token.is_empty()) || self.offline;
         CentralPanel::default().show(ctx, |ui| {
             ui.add_enabled_ui(enabled, |ui| {
                 TopBottomPanel::top("search-result").show_inside(ui, |ui| {
                     ui.horizontal(|ui| {
-                        ui.label("🔍搜索");
-                        let re = Regex::new(self.search_text.as_str());
-                        if !self.stock_list.is_empty() && (ui.text_edit_singleline(&mut self.search_text).changed() || self.search_text != self.stock_list_select_text
-                            || (self.stock_list_select_text.is_empty() && self.search_text != self.stock_list_select_text)) {
-                            let filter =
-                                if let Ok(re) = re.clone() {
-                                    let re = re.clone();
-                                    Some(move |text: &str| {
-                                        re.is_match(text)
-                                    })
-                                } else { None };
-                            let stock_list_select = self.stock_list.iter().filter(|s| {
-                                if let Some(filter) = &filter {
-                                    filter(&s.code) ||
-                                        filter(&s.symbol) ||
-                                        filter(&s.name)
-                                } else {
-                                    let filter = |text: &str| self.search_text.contains(text);
-                                    filter(&s.code) ||
-                                        filter(&s.symbol) ||
-                                        filter(&s.name)
-                                }
-                            });
-                            self.stock_list_select = stock_list_select.map(|x| x.clone()).collect();
-                            self.stock_list_select_text = self.search_text.to_string();
-                        }
-                        ui.add(Label::new(RichText::new(if self.search_text.is_empty() {
-                            "支持正则表达式检索"
-                        } else {
-                            if re.is_ok() {
-                                "正确的正则表达式"
-                            } else {
-                                "无效的正则表达式"
-                            }
-                        }).color(ui.visuals().weak_text_color())))
+                        self.search_ui(ui);
                     });
                 });
                 CentralPanel::default().show_inside(ui, |ui| {
@@ -122,21 +103,35 @@ impl eframe::App for FinancialAnalysis {
             });
             // ui.label(format!("windows: {}", self.history_views.len()));
         });
+        if self.show_profiles_window {
+            self.profiles_window(ctx);
+        }
         if !self.login_done {
-            if self.client.is_some() {
+            if self.connected() {
                 self.login_window(ctx);
             }
         }
-        if self.client.is_none() {
+        if !self.connected() && !self.offline {
             Window::new("连接中...")
                 .show(ctx, |ui| {
                     ui.horizontal(|ui| {
-                        ui.label("正在连接后端...");
-                        ui.spinner();
+                        match &self.connection_state {
+                            ConnectionState::Failed { error, .. } => {
+                                ui.label(RichText::new(format!("连接后端失败：{}", error)).color(ui.visuals().warn_fg_color));
+                            }
+                            _ => {
+                                ui.label("正在连接后端...");
+                                ui.spinner();
+                            }
+                        }
                     });
+                    if ui.button("离线模式").on_hover_text("使用本地缓存的数据，只能浏览").clicked() {
+                        self.enter_offline();
+                    }
                 });
         }
-        if self.stock_list_requesting {
+        // searches show their own spinner
+        if self.stock_list_requesting && self.stock_list_time.is_none() {
             Window::new("加载数据...")
                 .show(ctx, |ui| {
                     ui.horizontal(|ui| {
@@ -147,6 +142,7 @@ impl eframe::App for FinancialAnalysis {
         }
         // remove invalid windows inplace
         self.history_views.retain(|x| x.valid);
+        self.check_quote_subscription();
         if enabled {
             for view in &mut self.history_views {
                 if view.valid { view.window(ctx); }
@@ -156,6 +152,17 @@ impl eframe::App for FinancialAnalysis {
 
     /// Called by the frame work to save state before shutdown.
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        // tokens are kept in the encrypted credential file on native,
+        // on web only remembered ones go to local storage
+        #[cfg(target_arch = "wasm32")]
+        let forgotten: Vec<(usize, crate::financial_analysis::Token)> = self.profiles.iter_mut().enumerate()
+            .filter(|(_, x)| !x.remember)
+            .map(|(i, x)| (i, std::mem::take(&mut x.token)))
+            .collect();
         eframe::set_value(storage, eframe::APP_KEY, self);
+        #[cfg(target_arch = "wasm32")]
+        for (i, token) in forgotten {
+            self.profiles[i].token = token;
+        }
     }
 }
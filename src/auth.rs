@@ -0,0 +1,86 @@
+use std::sync::{mpsc, Arc, RwLock};
+use tonic::metadata::MetadataValue;
+use tonic::{Code, Request, Status};
+use crate::constants::TOKEN_REFRESH_BEFORE_SECONDS;
+use crate::financial_analysis::Token;
+use crate::message::Message;
+
+/// Session token shared by all api clients, updated on login and logout.
+pub type SharedToken = Arc<RwLock<Token>>;
+
+/// Sends current session token as `authorization: Bearer <token>` with every request.
+#[derive(Debug, Clone, Default)]
+pub struct AuthInterceptor {
+    pub token: SharedToken,
+}
+
+impl AuthInterceptor {
+    pub fn new(token: SharedToken) -> Self {
+        Self { token }
+    }
+}
+
+impl tonic::service::Interceptor for AuthInterceptor {
+    fn call(&mut self, mut req: Request<()>) -> Result<Request<()>, Status> {
+        let token = self.token.read().map(|x| x.clone()).unwrap_or_default();
+        if !token.is_empty() {
+            let value: MetadataValue<_> = format!("Bearer {}", token).parse()
+                .map_err(|_| Status::unauthenticated("invalid session token"))?;
+            req.metadata_mut().insert("authorization", value);
+        }
+        Ok(req)
+    }
+}
+
+/// Claims of the session token; the client cannot verify the signature, only reads them.
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct Claims {
+    #[serde(default, alias = "username")]
+    pub sub: String,
+    #[serde(default)]
+    pub exp: Option<u64>,
+    #[serde(default)]
+    pub iat: Option<u64>,
+}
+
+impl Claims {
+    pub fn expired(&self, now: u64) -> bool {
+        self.exp.map(|exp| exp <= now).unwrap_or(false)
+    }
+    pub fn need_refresh(&self, now: u64) -> bool {
+        self.exp.map(|exp| exp <= now + TOKEN_REFRESH_BEFORE_SECONDS).unwrap_or(false)
+    }
+}
+
+pub fn decode_claims(token: &str) -> anyhow::Result<Claims> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let mut validation = jsonwebtoken::Validation::default();
+        validation.insecure_disable_signature_validation();
+        validation.validate_exp = false;
+        validation.required_spec_claims.clear();
+        let data = jsonwebtoken::decode::<Claims>(token, &jsonwebtoken::DecodingKey::from_secret(&[]), &validation)?;
+        Ok(data.claims)
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        // `jsonwebtoken::decode` reads `SystemTime`, which panics on wasm
+        jsonwebtoken::decode_header(token)?;
+        let payload = token.split('.').nth(1).ok_or_else(|| anyhow::anyhow!("invalid token"))?;
+        let data = base64::decode_config(payload, base64::URL_SAFE_NO_PAD)?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+}
+
+/// Notify the app to log out when the server rejects current token.
+pub fn check_status(status: &Status, tx: &mpsc::Sender<Message>) {
+    match status.code() {
+        Code::Unauthenticated => {
+            let _ = tx.send(Message::Unauthenticated(status.message().to_string()));
+        }
+        Code::Unavailable => {
+            let _ = tx.send(Message::ConnectionLost(status.message().to_string()));
+        }
+        _ => {}
+    }
+}
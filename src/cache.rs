@@ -0,0 +1,310 @@
+use rpc::api::{StockListRequest, TradingHistoryResp, TradingHistoryType};
+use tracing::{debug, warn};
+use crate::constants::MARKET_CLOSE_UTC_SECONDS;
+use crate::message::{Message, ViewResponse};
+use crate::service::{ServiceRequest, ViewRequest};
+use crate::stock_search::unfiltered;
+use crate::utils::now_seconds;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CacheTtl {
+    Seconds(u64),
+    /// bars of today may still change before the market closes
+    UntilMarketClose,
+}
+
+impl CacheTtl {
+    pub fn expires_at(&self, now: u64) -> u64 {
+        match self {
+            CacheTtl::Seconds(seconds) => now + seconds,
+            CacheTtl::UntilMarketClose => {
+                let close = now - now % 86400 + MARKET_CLOSE_UTC_SECONDS;
+                if close > now { close } else { close + 86400 }
+            }
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct CacheEntry {
+    /// full key, files are named by its hash
+    #[serde(default)]
+    key: String,
+    stored_at: u64,
+    expires_at: u64,
+    /// base64 of the protobuf encoded response
+    data: String,
+}
+
+/// Key of a cached response, the RPC name followed by its parameters.
+pub fn cache_key(rpc: &str, params: &[&str]) -> String {
+    let mut key = rpc.to_string();
+    for param in params {
+        key.push('/');
+        key.push_str(param);
+    }
+    key
+}
+
+pub fn stock_list_key(request: &StockListRequest) -> String {
+    let statuses: Vec<String> = request.statuses.iter().map(|x| x.to_string()).collect();
+    cache_key("stock_list", &[&request.query, &request.market, &request.industry, &request.board, &statuses.join(","),
+        &request.page_token, &request.page_size.to_string()])
+}
+
+pub fn trading_history_key(symbol: &str, typ: TradingHistoryType) -> String {
+    cache_key("trading_history", &[symbol, &(typ as i32).to_string()])
+}
+
+pub fn stock_issue_key(code: &str) -> String {
+    cache_key("stock_issue", &[code])
+}
+
+pub fn guide_line_key(code: &str, year: &str) -> String {
+    cache_key("guide_line", &[code, year])
+}
+
+pub fn income_analysis_key(code: &str, years: u32) -> String {
+    cache_key("income_analysis", &[code, &years.to_string()])
+}
+
+/// Keys of income statement, balance sheet and cash flow statement.
+pub fn statement_keys(code: &str) -> [String; 3] {
+    ["income_statement", "balance_sheet", "cash_flow_statement"].map(|x| cache_key(x, &[code]))
+}
+
+/// Responses persisted in files on native and `localStorage` on web.
+///
+/// Entries are scoped by server, it holds no state in memory so clones are cheap
+/// and can be handed to every request. Reads and writes block on files, call them
+/// from request tasks, through [`blocking`] where it matters.
+#[derive(Debug, Clone, Default)]
+pub struct ResponseCache {
+    scope: String,
+}
+
+impl ResponseCache {
+    pub fn set_scope(&mut self, scope: &str) {
+        self.scope = scope.to_string();
+    }
+    /// Cached response of `key` with the time it was stored, expired ones only when `stale`.
+    pub fn get<T: prost::Message + Default>(&self, key: &str, stale: bool) -> Option<(T, u64)> {
+        let entry: CacheEntry = serde_json::from_str(&self.read(key)?).ok()?;
+        if entry.key != self.full_key(key) {
+            return None;
+        }
+        if !stale && entry.expires_at <= now_seconds() {
+            debug!("cache expired: {}", key);
+            return None;
+        }
+        let data = base64::decode(entry.data).ok()?;
+        Some((T::decode(data.as_slice()).ok()?, entry.stored_at))
+    }
+    pub fn put<T: prost::Message>(&self, key: &str, value: &T, ttl: CacheTtl) {
+        let now = now_seconds();
+        let entry = CacheEntry {
+            key: self.full_key(key),
+            stored_at: now,
+            expires_at: ttl.expires_at(now),
+            data: base64::encode(value.encode_to_vec()),
+        };
+        match serde_json::to_string(&entry) {
+            Ok(text) => self.write(key, &text),
+            Err(e) => warn!("cannot encode cache entry {}: {}", key, e),
+        }
+    }
+    /// Answer `request` from cache, `None` when any part of it is missing, or expired unless `stale`.
+    pub fn lookup(&self, request: &ServiceRequest, stale: bool) -> Option<Vec<Message>> {
+        let ok = || "".to_string();
+        let messages = match request {
+            ServiceRequest::StockList((generation, request)) => {
+                let (r, time) = self.get(&stock_list_key(request), stale)
+                    .or_else(|| {
+                        // offline, the whole list loaded from a server without search will do for the first page
+                        if stale && unfiltered(request) { self.get(&stock_list_key(&Default::default()), stale) } else { None }
+                    })?;
+                vec![Message::GotStockList((*generation, request.clone(), r, time))]
+            }
+            ServiceRequest::View((tag, request)) => {
+                let responses = match request {
+                    ViewRequest::TradingHistory((symbol, typ, _)) => {
+                        // always the whole history, replacing what the view has
+                        let (r, time): (TradingHistoryResp, u64) = self.get(&trading_history_key(symbol, *typ), stale)?;
+                        vec![(ViewResponse::TradingHistory((ok(), r.data, ok())), time)]
+                    }
+                    ViewRequest::StockIssue(code) => {
+                        let (r, time) = self.get(&stock_issue_key(code), stale)?;
+                        vec![(ViewResponse::StockIssue((r, ok())), time)]
+                    }
+                    ViewRequest::GuideLine((code, year)) => {
+                        let (r, time) = self.get(&guide_line_key(code, year), stale)?;
+                        vec![(ViewResponse::GuideLine((r, ok())), time)]
+                    }
+                    ViewRequest::IncomeAnalysis((code, years)) => {
+                        let (r, time) = self.get(&income_analysis_key(code, *years), stale)?;
+                        vec![(ViewResponse::IncomeAnalysis((r, ok())), time)]
+                    }
+                    ViewRequest::Statements(code) => {
+                        let [income, balance, cash_flow] = statement_keys(code);
+                        let (income, income_time) = self.get(&income, stale)?;
+                        let (balance, balance_time) = self.get(&balance, stale)?;
+                        let (cash_flow, cash_flow_time) = self.get(&cash_flow, stale)?;
+                        vec![
+                            (ViewResponse::IncomeStatement((income, ok())), income_time),
+                            (ViewResponse::BalanceSheet((balance, ok())), balance_time),
+                            (ViewResponse::CashFlowStatement((cash_flow, ok())), cash_flow_time),
+                        ]
+                    }
+                    ViewRequest::Overview(request) => {
+                        let (history, time): (TradingHistoryResp, u64) = self.get(&trading_history_key(&request.symbol, request.typ()), stale)?;
+                        let mut responses = vec![(ViewResponse::TradingHistory((ok(), history.data, ok())), time)];
+                        if request.issue {
+                            let (r, time) = self.get(&stock_issue_key(&request.code), stale)?;
+                            responses.push((ViewResponse::StockIssue((r, ok())), time));
+                        }
+                        if request.income_analysis {
+                            let (r, time) = self.get(&income_analysis_key(&request.code, request.income_years), stale)?;
+                            responses.push((ViewResponse::IncomeAnalysis((r, ok())), time));
+                        }
+                        responses
+                    }
+                    ViewRequest::Predict(_) => return None,
+                };
+                responses.into_iter().map(|(x, time)| Message::ViewResponse((*tag, x, time))).collect()
+            }
+            _ => return None,
+        };
+        debug!("cache hit: {:?}", request);
+        Some(messages)
+    }
+    fn full_key(&self, key: &str) -> String {
+        format!("{}/{}", self.scope, key)
+    }
+}
+
+/// Run `f` on the blocking pool on native, as cache files are read and written synchronously.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    tokio::task::spawn_blocking(f).await.expect("cache task panicked")
+}
+
+/// `localStorage` is synchronous and in memory, nothing to wait for.
+#[cfg(target_arch = "wasm32")]
+pub async fn blocking<T>(f: impl FnOnce() -> T) -> T {
+    f()
+}
+
+/// FNV-1a, stable across builds unlike `DefaultHasher`, so file names stay the same.
+#[cfg(not(target_arch = "wasm32"))]
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, b| (hash ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ResponseCache {
+    fn dir() -> Option<std::path::PathBuf> {
+        directories_next::ProjectDirs::from("", "", crate::constants::APP_NAME).map(|x| x.cache_dir().join("responses"))
+    }
+    /// Directory of entries of current server.
+    fn scope_dir(&self) -> Option<std::path::PathBuf> {
+        Self::dir().map(|x| x.join(format!("{:016x}", fnv1a(&self.scope))))
+    }
+    /// Keys are any text, the hashed name is checked against the key stored in the entry.
+    fn path(&self, key: &str) -> Option<std::path::PathBuf> {
+        self.scope_dir().map(|x| x.join(format!("{:016x}.json", fnv1a(key))))
+    }
+    fn read(&self, key: &str) -> Option<String> {
+        std::fs::read_to_string(self.path(key)?).ok()
+    }
+    fn write(&self, key: &str, text: &str) {
+        let Some(path) = self.path(key) else { return; };
+        let r = path.parent().map(std::fs::create_dir_all).unwrap_or(Ok(()))
+            .and_then(|_| std::fs::write(&path, text));
+        if let Err(e) = r {
+            warn!("cannot write cache {:?}: {}", path, e);
+        }
+    }
+    /// Remove entries of current server.
+    pub fn clear(&self) {
+        if let Some(dir) = self.scope_dir() {
+            if dir.exists() {
+                if let Err(e) = std::fs::remove_dir_all(&dir) {
+                    warn!("cannot clear cache {:?}: {}", dir, e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl ResponseCache {
+    const PREFIX: &'static str = "cache/";
+    fn storage() -> Option<web_sys::Storage> {
+        web_sys::window()?.local_storage().ok()?
+    }
+    fn read(&self, key: &str) -> Option<String> {
+        Self::storage()?.get_item(&format!("{}{}", Self::PREFIX, self.full_key(key))).ok()?
+    }
+    fn write(&self, key: &str, text: &str) {
+        let Some(storage) = Self::storage() else { return; };
+        // quota is a few megabytes, a failed write just misses the cache
+        if storage.set_item(&format!("{}{}", Self::PREFIX, self.full_key(key)), text).is_err() {
+            warn!("cannot write cache {}", key);
+        }
+    }
+    /// Remove entries of current server.
+    pub fn clear(&self) {
+        let Some(storage) = Self::storage() else { return; };
+        let prefix = format!("{}{}/", Self::PREFIX, self.scope);
+        let len = storage.length().unwrap_or(0);
+        let keys: Vec<String> = (0..len)
+            .filter_map(|i| storage.key(i).ok().flatten())
+            .filter(|x| x.starts_with(&prefix))
+            .collect();
+        for key in keys {
+            let _ = storage.remove_item(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rpc::api::StockListRequest;
+    use crate::cache::{stock_list_key, CacheTtl, ResponseCache};
+    use crate::constants::MARKET_CLOSE_UTC_SECONDS;
+
+    #[test]
+    fn until_market_close() {
+        let day = 19000 * 86400;
+        let ttl = CacheTtl::UntilMarketClose;
+        assert_eq!(ttl.expires_at(day), day + MARKET_CLOSE_UTC_SECONDS);
+        assert_eq!(ttl.expires_at(day + MARKET_CLOSE_UTC_SECONDS), day + 86400 + MARKET_CLOSE_UTC_SECONDS);
+        assert_eq!(CacheTtl::Seconds(10).expires_at(day), day + 10);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn paths_by_scope() {
+        let mut cache = ResponseCache::default();
+        cache.set_scope("http://localhost:50051");
+        let Some(path) = cache.path("stock_list/a/b") else { return; };
+        // would be the same with punctuation replaced
+        assert_ne!(Some(path.clone()), cache.path("stock_list/a_b"));
+        let mut other = cache.clone();
+        other.set_scope("https://a.chiro.work:50051");
+        assert_ne!(path.parent(), other.path("stock_list/a/b").as_deref().and_then(|x| x.parent()));
+        assert_eq!(Some(path), cache.path("stock_list/a/b"));
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn search_paths() {
+        let cache = ResponseCache::default();
+        let search = |query: &str| StockListRequest { query: query.to_string(), page_size: 100, ..Default::default() };
+        let (a, b) = (stock_list_key(&search("茅台")), stock_list_key(&search("平安")));
+        assert_ne!(a, b);
+        if let Some(path) = cache.path(&a) {
+            assert_ne!(Some(path), cache.path(&b));
+        }
+    }
+}
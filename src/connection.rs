@@ -0,0 +1,131 @@
+use egui::{Color32, RichText, Ui};
+use tracing::{info, warn};
+use crate::constants::{CONNECT_MAX_ATTEMPTS, CONNECT_RETRY_MAX_SECONDS};
+use crate::financial_analysis::FinancialAnalysis;
+use crate::service::ServiceRequest;
+use crate::utils::{format_duration, now_seconds};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionState {
+    Connecting { attempt: u32 },
+    Connected,
+    /// Last attempt failed, retrying at `retry_at`, or given up when it's `None`.
+    Failed { error: String, retry_at: Option<u64> },
+}
+
+impl Default for ConnectionState {
+    fn default() -> Self {
+        Self::Connecting { attempt: 1 }
+    }
+}
+
+/// Exponential backoff before next attempt: 1s, 2s, 4s... capped.
+pub fn backoff_seconds(attempt: u32) -> u64 {
+    (1u64 << attempt.saturating_sub(1).min(16)).min(CONNECT_RETRY_MAX_SECONDS)
+}
+
+/// Error message with its sources, transport errors hide the useful part there.
+pub fn error_chain(e: &dyn std::error::Error) -> String {
+    let mut text = e.to_string();
+    let mut source = e.source();
+    while let Some(e) = source {
+        text = format!("{}: {}", text, e);
+        source = e.source();
+    }
+    text
+}
+
+impl FinancialAnalysis {
+    /// Connect to server of current profile from the first attempt.
+    pub fn refresh_client(&mut self) {
+        self.connect_attempt = 0;
+        self.connect();
+    }
+    /// Start one connection attempt, results come back as
+    /// `ApiClientConnect` or `ConnectFailed` tagged with `connect_generation`.
+    pub fn connect(&mut self) {
+        self.connect_generation += 1;
+        self.connect_attempt += 1;
+        self.connection_state = ConnectionState::Connecting { attempt: self.connect_attempt };
+        info!("connecting to {} (attempt #{})...", self.profile().url(), self.connect_attempt);
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Err(e) = self.profile().endpoint() {
+            // retrying won't help until the profile is fixed
+            self.connection_state = ConnectionState::Failed { error: format!("{:#}", e), retry_at: None };
+            return;
+        }
+        self.call(ServiceRequest::Connect((self.connect_generation, self.profile().clone())));
+    }
+    pub fn connected(&self) -> bool {
+        self.connection_state == ConnectionState::Connected
+    }
+    pub fn on_connected(&mut self, generation: u64) {
+        if generation != self.connect_generation {
+            return;
+        }
+        self.connection_state = ConnectionState::Connected;
+        self.connect_attempt = 0;
+        self.health = Default::default();
+        self.server_info = Default::default();
+        self.request_server_info();
+        if !self.token.is_empty() {
+            if self.offline {
+                self.leave_offline();
+            }
+            self.load_stock_list();
+        }
+    }
+    pub fn on_connect_failed(&mut self, generation: u64, error: String) {
+        if generation != self.connect_generation {
+            return;
+        }
+        warn!("connect failed (attempt {}): {}", self.connect_attempt, error);
+        let retry_at = if self.connect_attempt < CONNECT_MAX_ATTEMPTS {
+            Some(now_seconds() + backoff_seconds(self.connect_attempt))
+        } else {
+            None
+        };
+        self.connection_state = ConnectionState::Failed { error, retry_at };
+    }
+    /// Server became unreachable while connected, reconnect from scratch.
+    pub fn on_connection_lost(&mut self, reason: String) {
+        if self.connection_state != ConnectionState::Connected {
+            return;
+        }
+        warn!("connection lost: {}", reason);
+        self.connect_attempt = 0;
+        self.connection_state = ConnectionState::Failed { error: reason, retry_at: Some(now_seconds()) };
+    }
+    /// Called every frame to fire scheduled retries.
+    pub fn check_connection(&mut self) {
+        if let ConnectionState::Failed { retry_at: Some(retry_at), .. } = &self.connection_state {
+            if now_seconds() >= *retry_at {
+                self.connect();
+            }
+        }
+    }
+    pub fn connection_status_ui(&mut self, ui: &mut Ui) {
+        match &self.connection_state {
+            ConnectionState::Connecting { attempt } => {
+                ui.spinner();
+                ui.label(if *attempt > 1 { format!("连接中 (第 {} 次)", attempt) } else { "连接中".to_string() });
+            }
+            ConnectionState::Connected => {
+                let version = if self.server_info.legacy { "未知".to_string() } else { self.server_info.version.to_string() };
+                ui.label(RichText::new("● 已连接").color(Color32::GREEN))
+                    .on_hover_text(format!("服务器版本: {}", version));
+            }
+            ConnectionState::Failed { error, retry_at } => {
+                let text = match retry_at {
+                    Some(retry_at) => format!("● 连接失败，{} 后重试", format_duration(retry_at.saturating_sub(now_seconds()))),
+                    None => "● 连接失败".to_string(),
+                };
+                ui.label(RichText::new(text).color(ui.visuals().warn_fg_color))
+                    .on_hover_text(error);
+            }
+        }
+        if ui.button("重新连接").clicked() {
+            self.refresh_client();
+        }
+    }
+}
@@ -1,7 +1,30 @@
 pub const APP_NAME: &'static str = "上市公司财务信息收集分析";
 pub const REPAINT_AFTER_SECONDS: f32 = 0.1;
 pub const LINE_WIDTH: f32 = 1.5;
-pub const AVAILABLE_API_HOSTS: &[&'static str] = &[
-    "localhost",
-    "a.chiro.work"
-];
\ No newline at end of file
+/// Refresh session token when it expires within this time.
+pub const TOKEN_REFRESH_BEFORE_SECONDS: u64 = 300;
+pub const TOKEN_REFRESH_RETRY_SECONDS: u64 = 30;
+/// Give up reconnecting after this many failed attempts in a row.
+pub const CONNECT_MAX_ATTEMPTS: u32 = 10;
+pub const CONNECT_RETRY_MAX_SECONDS: u64 = 32;
+pub const PING_INTERVAL_SECONDS: u64 = 5;
+/// Length of latency history kept for the debug panel.
+pub const PING_HISTORY_SECONDS: u64 = 300;
+/// Stock list changes with new listings only.
+pub const CACHE_TTL_STOCK_LIST_SECONDS: u64 = 86400;
+/// IPO data never changes once the stock is listed.
+pub const CACHE_TTL_IMMUTABLE_SECONDS: u64 = 30 * 86400;
+pub const CACHE_TTL_FINANCIAL_SECONDS: u64 = 86400;
+/// Quotes polling interval of clients without streaming.
+pub const QUOTE_POLL_SECONDS: u64 = 3;
+/// Wait before resubscribing after the quote stream broke.
+pub const QUOTE_RETRY_SECONDS: u64 = 5;
+/// How long a stock view flashes after a quote update.
+pub const QUOTE_FLASH_SECONDS: f64 = 0.6;
+pub const STOCK_LIST_PAGE_SIZE: u32 = 100;
+/// Wait for typing to pause before searching on server.
+pub const SEARCH_DEBOUNCE_SECONDS: f64 = 0.3;
+/// A-share market closes at 15:00 UTC+8.
+pub const MARKET_CLOSE_UTC_SECONDS: u64 = 7 * 3600;
+/// Years of guide lines fetched for valuation history.
+pub const VALUATION_YEARS: i32 = 10;
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use anyhow::{anyhow, Result};
+use egui::{RichText, Ui};
+use futures::channel::mpsc::UnboundedReceiver;
+use futures::StreamExt;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use tracing::{error, info};
+use crate::cache::blocking;
+use crate::constants::APP_NAME;
+use crate::financial_analysis::{FinancialAnalysis, Token};
+use crate::message::Message;
+use crate::password::password;
+use crate::profile::ConnectionProfile;
+use crate::service::ServiceRequest;
+
+pub const CREDENTIAL_FILE: &str = "credentials.json";
+const PBKDF2_ITERATIONS: u32 = 100_000;
+const SALT_LEN: usize = 16;
+
+/// Layout of the credential file, `data` is AES-256-GCM sealed json of [`Credentials`].
+#[derive(serde::Deserialize, serde::Serialize)]
+struct EncryptedFile {
+    salt: Vec<u8>,
+    nonce: Vec<u8>,
+    data: Vec<u8>,
+}
+
+/// Tokens of remembered accounts, keyed by `username@url`.
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct Credentials {
+    pub tokens: HashMap<String, Token>,
+}
+
+/// Credential store of current session, kept out of eframe persistence.
+#[derive(Debug, Default)]
+pub struct CredentialState {
+    /// `Some` after unlocking the file, or when there is no file yet
+    pub credentials: Option<Credentials>,
+    pub passphrase: String,
+    pub exists: bool,
+    /// user chose not to unlock saved accounts this time
+    pub skipped: bool,
+    pub unlocking: bool,
+    pub error: String,
+}
+
+/// Work on the credential file, done by [`credential_worker`] as key derivation takes a while.
+pub enum CredentialJob {
+    /// passphrase, answered with `Message::CredentialsUnlocked`
+    Unlock(String),
+    /// (credentials, passphrase), answered with `Message::CredentialsSaved`
+    Save((Credentials, String)),
+}
+
+/// Keeps passphrase and tokens out of logs.
+impl Debug for CredentialJob {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CredentialJob::Unlock(_) => write!(f, "Unlock"),
+            CredentialJob::Save((credentials, _)) => write!(f, "Save({} tokens)", credentials.tokens.len()),
+        }
+    }
+}
+
+pub fn credential_path() -> Option<PathBuf> {
+    directories_next::ProjectDirs::from("", "", APP_NAME).map(|x| x.data_dir().join(CREDENTIAL_FILE))
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<LessSafeKey> {
+    let mut key = [0u8; 32];
+    ring::pbkdf2::derive(ring::pbkdf2::PBKDF2_HMAC_SHA256, NonZeroU32::new(PBKDF2_ITERATIONS).unwrap(),
+                         salt, passphrase.as_bytes(), &mut key);
+    let key = UnboundKey::new(&AES_256_GCM, &key).map_err(|_| anyhow!("invalid key"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+impl Credentials {
+    pub fn key(profile: &ConnectionProfile) -> String {
+        format!("{}@{}", profile.username, profile.url())
+    }
+    pub fn load(passphrase: &str) -> Result<Self> {
+        let path = credential_path().ok_or_else(|| anyhow!("no data directory"))?;
+        let file: EncryptedFile = serde_json::from_slice(&std::fs::read(path)?)?;
+        let key = derive_key(passphrase, &file.salt)?;
+        let nonce = Nonce::try_assume_unique_for_key(&file.nonce).map_err(|_| anyhow!("invalid nonce"))?;
+        let mut data = file.data;
+        let plain = key.open_in_place(nonce, Aad::empty(), &mut data)
+            .map_err(|_| anyhow!("wrong passphrase or broken file"))?;
+        Ok(serde_json::from_slice(plain)?)
+    }
+    pub fn save(&self, passphrase: &str) -> Result<()> {
+        let path = credential_path().ok_or_else(|| anyhow!("no data directory"))?;
+        let mut salt = [0u8; SALT_LEN];
+        let mut nonce = [0u8; NONCE_LEN];
+        getrandom::getrandom(&mut salt)?;
+        getrandom::getrandom(&mut nonce)?;
+        let key = derive_key(passphrase, &salt)?;
+        let mut data = serde_json::to_vec(self)?;
+        key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+            .map_err(|_| anyhow!("encrypt failed"))?;
+        let file = EncryptedFile { salt: salt.to_vec(), nonce: nonce.to_vec(), data };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_json::to_vec(&file)?)?;
+        Ok(())
+    }
+}
+
+/// Do jobs one at a time on the blocking pool, so the file is written in the order of saves.
+/// Failures are answered with `Message::CredentialError`.
+pub async fn credential_worker(mut rx: UnboundedReceiver<CredentialJob>, tx: mpsc::Sender<Message>) {
+    while let Some(job) = rx.next().await {
+        let msg = blocking(move || match job {
+            CredentialJob::Unlock(passphrase) => Credentials::load(&passphrase).map(Message::CredentialsUnlocked),
+            CredentialJob::Save((credentials, passphrase)) => credentials.save(&passphrase).map(|_| Message::CredentialsSaved),
+        }).await;
+        let _ = tx.send(msg.unwrap_or_else(|e| Message::CredentialError(e.to_string())));
+    }
+}
+
+impl FinancialAnalysis {
+    pub fn init_credentials(&mut self) {
+        self.credential.exists = credential_path().map(|x| x.exists()).unwrap_or(false);
+        if !self.credential.exists {
+            self.credential.credentials = Some(Default::default());
+        }
+    }
+    /// Saved accounts exist but are not unlocked yet.
+    pub fn credential_locked(&self) -> bool {
+        self.credential.credentials.is_none() && !self.credential.skipped
+    }
+    pub fn unlock_credentials(&mut self) {
+        self.credential.unlocking = true;
+        self.call(ServiceRequest::Credential(CredentialJob::Unlock(self.credential.passphrase.to_string())));
+    }
+    pub fn on_credentials_unlocked(&mut self, credentials: Credentials) {
+        info!("unlocked {} saved tokens", credentials.tokens.len());
+        for profile in self.profiles.iter_mut().filter(|x| x.remember) {
+            if let Some(token) = credentials.tokens.get(&Credentials::key(profile)) {
+                profile.token = token.to_string();
+            }
+        }
+        self.credential.credentials = Some(credentials);
+        self.credential.unlocking = false;
+        self.credential.error.clear();
+        self.restore_session();
+    }
+    pub fn on_credentials_saved(&mut self) {
+        self.credential.exists = true;
+        self.credential.error.clear();
+    }
+    pub fn on_credential_error(&mut self, error: String) {
+        error!("credentials: {}", error);
+        self.credential.unlocking = false;
+        self.credential.error = error;
+    }
+    /// Write token of current profile to the credential file, or drop it if not remembered.
+    pub fn remember_token(&mut self) {
+        let profile = self.profile().clone();
+        let passphrase = self.credential.passphrase.to_string();
+        let credentials = match &mut self.credential.credentials {
+            Some(credentials) => credentials,
+            None => return,
+        };
+        let key = Credentials::key(&profile);
+        if profile.remember && !profile.token.is_empty() {
+            if passphrase.is_empty() {
+                self.credential.error = "未设置本地加密口令，登录信息不会被保存".to_string();
+                return;
+            }
+            credentials.tokens.insert(key, profile.token);
+        } else if credentials.tokens.remove(&key).is_none() || passphrase.is_empty() {
+            return;
+        }
+        let job = CredentialJob::Save((credentials.clone(), passphrase));
+        self.call(ServiceRequest::Credential(job));
+    }
+    /// Unlock prompt or "remember me" options in login window.
+    pub fn credential_ui(&mut self, ui: &mut Ui) {
+        if self.credential_locked() {
+            ui.label("已保存的登录信息已加密，输入口令解锁：");
+            ui.add(password(&mut self.credential.passphrase));
+            ui.horizontal(|ui| {
+                ui.add_enabled_ui(!self.credential.unlocking, |ui| {
+                    if ui.button("解锁").clicked() {
+                        self.unlock_credentials();
+                    }
+                });
+                if ui.button("跳过").clicked() {
+                    self.credential.skipped = true;
+                }
+                if self.credential.unlocking {
+                    ui.spinner();
+                }
+            });
+        } else {
+            let unlocked = self.credential.credentials.is_some();
+            ui.add_enabled_ui(unlocked, |ui| {
+                ui.checkbox(&mut self.profile_mut().remember, "记住我")
+                    .on_disabled_hover_text("未解锁已保存的登录信息");
+            });
+            if unlocked && self.profile().remember {
+                ui.horizontal(|ui| {
+                    ui.label("本地加密口令");
+                    ui.add(password(&mut self.credential.passphrase));
+                });
+            }
+        }
+        if !self.credential.error.is_empty() {
+            ui.label(RichText::new(&self.credential.error).color(ui.visuals().warn_fg_color));
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use crate::financial_analysis::FinancialAnalysis;
 use crate::run_mode::RunMode;
+use crate::service::ServiceRequest;
 use egui::Ui;
 
 impl FinancialAnalysis {
@@ -37,6 +38,19 @@ impl FinancialAnalysis {
                 ui.ctx().memory_mut(|mem| *mem = Default::default());
             }
         });
+        ui.collapsing(format!("📶 {}", t!("debug.latency_history")), |ui| {
+            self.health.graph(ui);
+        });
+        ui.collapsing(t!("debug.rpc_timeouts"), |ui| {
+            let timeouts = self.rpc_timeouts;
+            self.rpc_timeouts.ui(ui);
+            if timeouts != self.rpc_timeouts {
+                self.call(ServiceRequest::SetTimeouts(self.rpc_timeouts));
+            }
+        });
+        if ui.button(t!("debug.clear_cache")).clicked() {
+            self.call(ServiceRequest::ClearCache);
+        }
         egui::warn_if_debug_build(ui);
     }
 }
@@ -1,21 +1,28 @@
 use std::sync::mpsc;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::credential::CredentialState;
+use crate::auth::{decode_claims, AuthInterceptor, Claims, SharedToken};
+use crate::connection::ConnectionState;
+use crate::constants::TOKEN_REFRESH_RETRY_SECONDS;
 use crate::frame_history::FrameHistory;
+use crate::health::Health;
+use crate::server_info::ServerInfo;
+use crate::profile::ConnectionProfile;
+use crate::request::RpcTimeouts;
 use crate::run_mode::RunMode;
-use egui::{Direction, FontData, FontDefinitions, FontFamily, Label, Layout, Sense, Ui};
+use egui::{Direction, FontData, FontDefinitions, FontFamily, Label, Layout, RichText, Sense, Ui};
 use egui_extras::{Column, TableBuilder};
 #[cfg(not(target_arch = "wasm32"))]
 use lazy_static::lazy_static;
 use num_traits::Float;
-use rpc::API_PORT;
 use tracing::{info, warn};
-use crate::message::{Channel, Message};
-use crate::message::Message::ApiClientConnect;
-use crate::service::Service;
+use crate::message::{Message, ViewId};
+use crate::service::{Service, ServiceHandle, ServiceRequest};
 use rpc::api::api_rpc_client::ApiRpcClient;
-use rpc::api::{StockListResp, StockResp};
-use tonic::Request;
+use rpc::api::StockResp;
+use crate::stock_search::{name_of, status_text, StockSearch, BOARDS, MARKETS};
 use crate::stock_view::StockView;
-use crate::utils::{execute, get_random_u32};
+use crate::utils::now_seconds;
 
 #[cfg(not(target_arch = "wasm32"))]
 lazy_static! {
@@ -29,7 +36,7 @@ pub type ApiChannel = tonic::transport::Channel;
 pub type ApiChannel = tonic_web_wasm_client::Client;
 
 // pub type MainApiClient = rpc::api::api_rpc_client::ApiRpcClient<ApiChannel>;
-pub type MainApiClient = ApiRpcClient<tonic::codegen::InterceptedService<ApiChannel, fn(tonic::Request<()>) -> Result<tonic::Request<()>, tonic::Status>>>;
+pub type MainApiClient = ApiRpcClient<tonic::codegen::InterceptedService<ApiChannel, AuthInterceptor>>;
 pub type RegisterApiClient = rpc::api::register_client::RegisterClient<ApiChannel>;
 
 pub type Token = String;
@@ -38,8 +45,18 @@ pub type Token = String;
 #[derive(serde::Deserialize, serde::Serialize)]
 #[serde(default)] // if we add new fields, give them default values when deserializing old state
 pub struct FinancialAnalysis {
+    /// token of current profile, persisted in `profiles`
+    #[serde(skip)]
     pub token: Token,
     #[serde(skip)]
+    pub session_token: SharedToken,
+    #[serde(skip)]
+    pub claims: Option<Claims>,
+    #[serde(skip)]
+    pub refreshing_token: bool,
+    #[serde(skip)]
+    pub token_refresh_retry_at: u64,
+    #[serde(skip)]
     pub login_done: bool,
     #[serde(skip)]
     pub login_error: String,
@@ -49,59 +66,131 @@ pub struct FinancialAnalysis {
     pub frame_history: FrameHistory,
     pub enable_debug_panel: bool,
 
+    /// responses from service, drained every frame
     #[serde(skip)]
-    pub channel: Option<Channel>,
+    pub inbox: Option<mpsc::Receiver<Message>>,
     #[serde(skip)]
-    pub loop_tx: Option<mpsc::Sender<Message>>,
+    pub service: Option<ServiceHandle>,
 
     // login inputs
     pub input_username: String,
     #[serde(skip)]
     pub input_password: String,
     #[serde(skip)]
-    pub client: Option<MainApiClient>,
+    pub input_password_confirm: String,
+    #[serde(skip)]
+    pub register_mode: bool,
+    #[serde(skip)]
+    pub registering: bool,
+    /// (success, message) of last registration
+    #[serde(skip)]
+    pub register_message: Option<(bool, String)>,
+    #[serde(skip)]
+    pub connection_state: ConnectionState,
+    /// bumped on every connection attempt to drop results of stale ones
+    #[serde(skip)]
+    pub connect_generation: u64,
+    #[serde(skip)]
+    pub connect_attempt: u32,
+    #[serde(skip)]
+    pub health: Health,
+    #[serde(skip)]
+    pub server_info: ServerInfo,
+    /// browsing cached data without server
+    #[serde(skip)]
+    pub offline: bool,
     #[serde(skip)]
     pub stock_list: Vec<StockResp>,
     #[serde(skip)]
     pub stock_list_requesting: bool,
     #[serde(skip)]
+    pub stock_list_error: String,
+    /// time the stock list was fetched from server
+    #[serde(skip)]
+    pub stock_list_time: Option<u64>,
+    #[serde(skip)]
     pub stock_list_select: Vec<StockResp>,
     #[serde(skip)]
     pub stock_list_select_text: String,
 
     pub search_text: String,
     #[serde(skip)]
+    pub search: StockSearch,
+    #[serde(skip)]
     pub history_views: Vec<StockView>,
+    /// id of the next opened stock view
+    #[serde(skip)]
+    pub next_view_id: ViewId,
+    /// symbols of current quote subscription
+    #[serde(skip)]
+    pub quote_symbols: Vec<String>,
 
     #[serde(skip)]
     pub stock_list_popular: Vec<StockResp>,
 
-    pub api_host: String,
+    pub rpc_timeouts: RpcTimeouts,
+    pub profiles: Vec<ConnectionProfile>,
+    pub profile_index: usize,
+    #[serde(skip)]
+    pub show_profiles_window: bool,
+    /// server of states saved before profiles, moved into a profile by `fix_profiles`
+    #[serde(default, skip_serializing)]
+    pub api_host: Option<String>,
+    /// token of states saved before profiles, moved like `api_host`
+    #[serde(default, skip_serializing, rename = "token")]
+    pub legacy_token: Option<Token>,
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    pub credential: CredentialState,
 }
 
 impl Default for FinancialAnalysis {
     fn default() -> Self {
         Self {
             token: "".to_string(),
+            session_token: Default::default(),
+            claims: None,
+            refreshing_token: false,
+            token_refresh_retry_at: 0,
             login_done: false,
             login_error: "".to_string(),
             run_mode: Default::default(),
             frame_history: Default::default(),
             enable_debug_panel: true,
-            channel: None,
-            loop_tx: None,
+            inbox: None,
+            service: None,
             input_username: "test".to_string(),
             input_password: "test".to_string(),
-            // client: None,
-            client: None,
+            input_password_confirm: "".to_string(),
+            register_mode: false,
+            registering: false,
+            register_message: None,
+            connection_state: Default::default(),
+            connect_generation: 0,
+            connect_attempt: 0,
+            health: Default::default(),
+            server_info: Default::default(),
+            offline: false,
             stock_list: vec![],
             stock_list_requesting: false,
+            stock_list_error: "".to_string(),
+            stock_list_time: None,
             stock_list_select: vec![],
             stock_list_select_text: "".to_string(),
             search_text: "".to_string(),
+            search: Default::default(),
             history_views: vec![],
+            next_view_id: 0,
+            quote_symbols: vec![],
             stock_list_popular: vec![],
-            api_host: "localhost".to_string(),
+            rpc_timeouts: Default::default(),
+            profiles: ConnectionProfile::defaults(),
+            profile_index: 0,
+            show_profiles_window: false,
+            api_host: None,
+            legacy_token: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            credential: Default::default(),
         }
     }
 }
@@ -135,199 +224,224 @@ impl FinancialAnalysis {
         // def.client = Some(crate::rpc_client::get_client());
         def.init()
     }
-    pub fn refresh_client(&mut self) {
-        // try to connect server
-        let addr = format!("http://{}:{}", self.api_host, API_PORT);
+    pub fn init(mut self) -> Self {
+        let (tx, rx) = mpsc::channel();
+        // launch service
+        self.service = Some(Service::start(tx, self.session_token.clone()));
+        self.inbox = Some(rx);
+        self.call(ServiceRequest::SetTimeouts(self.rpc_timeouts));
+        self.fix_profiles();
         #[cfg(not(target_arch = "wasm32"))]
-        {
-            if let Some(tx) = self.loop_tx.clone() {
-                RT.spawn(async move {
-                    info!("preparing main api client...");
-                    // let client = rpc::api::api_rpc_client::ApiRpcClient::new(tonic::transport::Endpoint::new(addr).unwrap().connect().await.unwrap());
-                    let client: MainApiClient = rpc::api::api_rpc_client::ApiRpcClient::with_interceptor(
-                        tonic::transport::Endpoint::new(addr).unwrap().connect().await.unwrap(),
-                        // tonic::transport::Channel::from_static("http://127.0.0.1:51411").connect().await.unwrap(),
-                        move |mut req: tonic::Request<()>| {
-                            let token: tonic::metadata::MetadataValue<_> = "token".parse().unwrap();
-                            req.metadata_mut().insert("authorization", token.clone());
-                            Ok(req)
-                        },
-                    );
-                    info!("got api client: {:?}", client);
-                    tx.send(ApiClientConnect(client)).unwrap();
-                });
-            }
+        self.init_credentials();
+        self.restore_session();
+        self.refresh_client();
+        self
+    }
+    /// Load account of current profile, logging out if its token is expired.
+    ///
+    /// A token without readable claims has unknown expiry, the server tells with `Unauthenticated`.
+    pub fn restore_session(&mut self) {
+        let profile = self.profile().clone();
+        if !profile.username.is_empty() {
+            self.input_username = profile.username;
         }
-        #[cfg(target_arch = "wasm32")]
-        {
-            // let client = rpc::api::api_rpc_client::ApiRpcClient::new(tonic_web_wasm_client::Client::new(addr));
-            let inner = tonic_web_wasm_client::Client::new(addr);
-            let client: MainApiClient = rpc::api::api_rpc_client::ApiRpcClient::with_interceptor(
-                inner,
-                move |mut req: tonic::Request<()>| {
-                    let token: tonic::metadata::MetadataValue<_> = "token".parse().unwrap();
-                    req.metadata_mut().insert("authorization", token.clone());
-                    Ok(req)
-                },
-            );
-            info!("got api client: {:?}", client);
-            self.client = Some(client);
-            self.load_stock_list();
+        self.set_token(profile.token);
+        self.login_done = false;
+        if !self.token.is_empty() {
+            match &self.claims {
+                Some(claims) if claims.expired(now_seconds()) => {
+                    warn!("persisted token is expired");
+                    self.logout();
+                }
+                _ => self.login_done = true,
+            }
         }
     }
-    pub fn init(mut self) -> Self {
-        let (channel_req_tx, channel_req_rx) = mpsc::channel();
-        let (channel_resp_tx, channel_resp_rx) = mpsc::channel();
 
-        // launch service
-        Service::start(Channel {
-            tx: channel_resp_tx.clone(),
-            rx: channel_req_rx,
-        });
-        self.channel = Some(Channel {
-            tx: channel_req_tx,
-            rx: channel_resp_rx,
-        });
-        self.loop_tx = Some(channel_resp_tx.clone());
-        self.refresh_client();
-        // TODO: dynamic check token
-        if !self.token.is_empty() {
-            self.login_done = true;
+    /// Update persisted token and the one sent by api clients.
+    pub fn set_token(&mut self, token: Token) {
+        if let Ok(mut session_token) = self.session_token.write() {
+            *session_token = token.clone();
+        }
+        self.profile_mut().token = token.clone();
+        self.claims = if token.is_empty() {
+            None
+        } else {
+            match decode_claims(&token) {
+                Ok(claims) => Some(claims),
+                Err(e) => {
+                    warn!("cannot decode token: {}", e);
+                    None
+                }
+            }
+        };
+        self.token = token;
+    }
+    /// Log out on expiry, and refresh the token silently before that.
+    pub fn check_token(&mut self) {
+        let claims = match &self.claims {
+            Some(claims) if !self.token.is_empty() => claims,
+            _ => return,
+        };
+        let now = now_seconds();
+        if claims.expired(now) {
+            self.logout();
+            self.login_error = "登录已过期，请重新登录".to_string();
+        } else if claims.need_refresh(now) && !self.refreshing_token && now >= self.token_refresh_retry_at {
+            self.refresh_token();
+        }
+    }
+    pub fn refresh_token(&mut self) {
+        if self.connected() {
+            self.refreshing_token = true;
+            self.call(ServiceRequest::RefreshToken);
+        }
+    }
+    pub fn call(&self, request: ServiceRequest) {
+        if let Some(service) = &self.service {
+            service.send(request);
         }
-        self
+    }
+    pub fn logout(&mut self) {
+        self.set_token("".to_string());
+        self.login_done = false;
+        #[cfg(not(target_arch = "wasm32"))]
+        self.remember_token();
     }
 
     pub fn message_handler(&mut self, msg: Message) {
         match msg {
-            ApiClientConnect(client) => {
-                info!("set client: {:?}", client);
-                self.client = Some(client);
-            }
+            Message::ApiClientConnect(generation) => self.on_connected(generation),
+            Message::ConnectFailed((generation, error)) => self.on_connect_failed(generation, error),
+            Message::ConnectionLost(reason) => self.on_connection_lost(reason),
+            Message::Pong((latency, error)) => self.health.on_pong(latency, error),
+            Message::GotServerInfo(resp) => self.set_server_info(resp),
             Message::LoginDone(token) => {
                 info!("token: {:?}", token);
                 self.login_done = true;
-                self.token = token.into();
+                self.login_error.clear();
+                self.profile_mut().username = self.input_username.clone();
+                self.set_token(token);
+                #[cfg(not(target_arch = "wasm32"))]
+                self.remember_token();
                 self.load_stock_list();
             }
             Message::LoginError(reason) => {
                 self.login_done = false;
                 self.login_error = reason.into();
             }
-            Message::GotStockList(stock) => {
-                info!("GotStockList");
-                self.stock_list = stock.data;
-                self.stock_list_requesting = false;
-                // randomly select some stocks to popular
-                if self.stock_list.len() > 0 {
-                    let n = 6;
-                    let mut data = vec![];
-                    for _ in 0..n {
-                        data.push(self.stock_list.get(get_random_u32() as usize % self.stock_list.len()).unwrap().clone());
-                    }
-                    self.stock_list_popular = data;
-                }
-            }
-            Message::GotTradingHistory(d) => {
-                // dispatch messages
-                let mut target = None;
-                for view in &mut self.history_views {
-                    if view.stock.symbol == d.0 {
-                        target = Some(view);
-                    }
-                }
-                if let Some(target) = target {
-                    target.message_handler(Message::GotTradingHistory(d));
+            Message::RegisterDone(resp) => {
+                self.registering = false;
+                if resp.err {
+                    self.register_message = Some((false, resp.reason));
+                } else {
+                    self.register_message = Some((true, "注册成功，请登录".to_string()));
+                    self.register_mode = false;
+                    self.input_password_confirm.clear();
                 }
             }
-            Message::GotPredicts(d) => {
-                // dispatch messages
-                let mut target = None;
-                for view in &mut self.history_views {
-                    if view.stock.symbol == d.0 {
-                        target = Some(view);
-                    }
-                }
-                if let Some(target) = target {
-                    target.message_handler(Message::GotPredicts(d));
-                }
+            Message::TokenRefreshed(token) => {
+                info!("token refreshed");
+                self.refreshing_token = false;
+                // a new token may still expire soon, don't refresh it again every frame
+                self.token_refresh_retry_at = now_seconds() + TOKEN_REFRESH_RETRY_SECONDS;
+                self.set_token(token);
+                #[cfg(not(target_arch = "wasm32"))]
+                self.remember_token();
             }
-            Message::GotStockIssue(d) => {
-                // dispatch messages
-                let mut target = None;
-                for view in &mut self.history_views {
-                    if view.stock.code == d.0 {
-                        target = Some(view);
-                    }
-                }
-                if let Some(target) = target {
-                    target.message_handler(Message::GotStockIssue(d));
-                }
+            Message::TokenRefreshError(reason) => {
+                warn!("refresh token failed: {}", reason);
+                self.refreshing_token = false;
+                self.token_refresh_retry_at = now_seconds() + TOKEN_REFRESH_RETRY_SECONDS;
             }
-            Message::GotGuideLine(d) => {
-                // dispatch messages
-                let mut target = None;
-                for view in &mut self.history_views {
-                    if view.stock.code == d.0 {
-                        target = Some(view);
-                    }
-                }
-                if let Some(target) = target {
-                    target.message_handler(Message::GotGuideLine(d));
+            Message::Unauthenticated(reason) => {
+                if !self.token.is_empty() {
+                    warn!("unauthenticated: {}", reason);
+                    self.logout();
+                    self.login_error = format!("登录已失效，请重新登录（{}）", reason);
                 }
             }
-            Message::GotIncomeAnalysis(d) => {
-                // dispatch messages
-                let mut target = None;
-                for view in &mut self.history_views {
-                    if view.stock.code == d.0 {
-                        target = Some(view);
-                    }
-                }
-                if let Some(target) = target {
-                    target.message_handler(Message::GotIncomeAnalysis(d));
+            Message::GotStockList((generation, request, stock, time)) => self.on_stock_list(generation, request, stock, time),
+            Message::GotQuote(quote) => self.on_quote(quote),
+            Message::StockListError((generation, error)) => self.on_stock_list_error(generation, error),
+            Message::ViewResponse((tag, response, time)) => {
+                // closed windows are gone, their responses are simply dropped
+                if let Some(view) = self.history_views.iter_mut().find(|x| x.id == tag.view) {
+                    view.response_handler(tag.request, response, time);
                 }
             }
+            #[cfg(not(target_arch = "wasm32"))]
+            Message::CredentialsUnlocked(credentials) => self.on_credentials_unlocked(credentials),
+            #[cfg(not(target_arch = "wasm32"))]
+            Message::CredentialsSaved => self.on_credentials_saved(),
+            #[cfg(not(target_arch = "wasm32"))]
+            Message::CredentialError(error) => self.on_credential_error(error),
         }
     }
     pub fn stock_list(&self, ui: &mut Ui, data: &Vec<StockResp>, on_click: impl FnOnce(StockResp), expand: bool) {
         pub const SIGNAL_HEIGHT_DEFAULT: f32 = 30.0;
-        let rect_max = ui.max_rect();
         let label_width = 64.0;
         let mut set_stock = None;
-        let table = TableBuilder::new(ui)
+        // details only fit in the expanded list
+        let detail_widths = [56.0, 56.0, 96.0, 88.0];
+        let mut table = TableBuilder::new(ui)
             .striped(true)
             .resizable(false)
             // .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
             .cell_layout(Layout::centered_and_justified(Direction::TopDown))
             .column(Column::exact(label_width).resizable(false))
-            .column(Column::exact(label_width).resizable(false))
-            .column(Column::exact(if expand { rect_max.width() - label_width - label_width } else { label_width }).resizable(false))
+            .column(Column::exact(label_width).resizable(false));
+        if expand {
+            table = table.column(Column::exact(96.0).resizable(false));
+            for width in detail_widths {
+                table = table.column(Column::exact(width).resizable(false));
+            }
+            table = table.column(Column::remainder().resizable(false));
+        } else {
+            table = table.column(Column::exact(label_width).resizable(false));
+        }
+        let table = table
             .min_scrolled_height(0.0)
             .max_scroll_height(f32::infinity());
         table.header(SIGNAL_HEIGHT_DEFAULT, |mut header| {
-            header.col(|ui| {
-                ui.label("代码");
-            });
-            header.col(|ui| {
-                ui.label("代号");
-            });
-            header.col(|ui| {
-                ui.label("名称");
-            });
+            let mut titles = vec!["代码", "代号", "名称"];
+            if expand {
+                titles.extend(["交易所", "板块", "行业", "上市日期", "状态"]);
+            }
+            for title in titles {
+                header.col(|ui| {
+                    ui.label(title);
+                });
+            }
         })
             .body(|body| {
                 body.heterogeneous_rows((0..data.len()).map(|_| SIGNAL_HEIGHT_DEFAULT), |row_index, mut row| {
                     if let Some(stock) = data.get(row_index) {
                         let mut r = None;
-                        let mut add_label = |text: &str, ui: &mut Ui| {
+                        let mut add_label = |text: RichText, ui: &mut Ui| {
                             let resp = ui.add(Label::new(text).sense(Sense::click()));
                             if resp.double_clicked() {
                                 r = Some(resp);
                             }
                         };
-                        row.col(|ui| add_label(stock.code.as_str(), ui));
-                        row.col(|ui| add_label(stock.symbol.as_str(), ui));
-                        row.col(|ui| add_label(stock.name.as_str(), ui));
+                        let mut texts = vec![stock.code.as_str(), stock.symbol.as_str(), stock.name.as_str()];
+                        if expand {
+                            fn or_dash(text: &str) -> &str {
+                                if text.is_empty() { "-" } else { text }
+                            }
+                            texts.extend([
+                                or_dash(name_of(&MARKETS, &stock.exchange)),
+                                or_dash(name_of(&BOARDS, &stock.board)),
+                                or_dash(&stock.industry),
+                                or_dash(&stock.list_date),
+                            ]);
+                        }
+                        for text in texts {
+                            row.col(|ui| add_label(RichText::new(text), ui));
+                        }
+                        if expand {
+                            row.col(|ui| add_label(status_text(ui, stock.status()), ui));
+                        }
                         if let Some(r) = r {
                             if r.double_clicked() {
                                 if !self.history_views.iter().any(|x| x.stock.symbol == stock.symbol) {
@@ -349,7 +463,8 @@ impl FinancialAnalysis {
             set_stock = Some(stock);
         }, true);
         if let Some(stock) = set_stock {
-            self.history_views.push(StockView::new(stock, self.client.clone(), self.loop_tx.clone()));
+            let view = self.new_stock_view(stock);
+            self.history_views.push(view);
         }
     }
     pub fn stock_list_popular_view(&mut self, ui: &mut Ui) {
@@ -359,47 +474,48 @@ impl FinancialAnalysis {
             set_stock = Some(stock);
         }, false);
         if let Some(stock) = set_stock {
-            self.history_views.push(StockView::new(stock, self.client.clone(), self.loop_tx.clone()));
+            let view = self.new_stock_view(stock);
+            self.history_views.push(view);
         }
     }
-    pub fn load_stock_list(&mut self) {
-        if let Some(mut client) = self.client.clone() {
-            let tx = self.loop_tx.as_ref().map(|x| x.clone());
-            self.stock_list_requesting = true;
-            execute(async move {
-                info!("requesting stock list");
-                let r = client.stock_list(Request::new(())).await;
-                if let Ok(stock) = r {
-                    let stock: StockListResp = stock.into_inner();
-                    info!("got stock_list: {}", stock.data.len());
-                    if let Some(tx) = tx {
-                        tx.send(Message::GotStockList(stock)).unwrap();
-                    }
-                }
-            });
-        } else {
-            warn!("no client when updating stock!");
-        }
+    pub fn new_stock_view(&mut self, stock: StockResp) -> StockView {
+        self.next_view_id += 1;
+        let mut view = StockView::new(self.next_view_id, stock, self.service.clone());
+        view.set_server_info(self.server_info.clone());
+        view.offline = self.offline;
+        view
     }
 }
 
 #[cfg(not(target_arch = "wasm32"))]
 #[cfg(test)]
 mod test {
-    use rpc::api::LoginRegisterRequest;
-    use rpc::API_PORT;
+    use std::sync::{Arc, RwLock};
+    use rpc::api::{LoginRegisterRequest, StockListRequest};
     use tracing::info;
-    use crate::financial_analysis::RegisterApiClient;
+    use crate::auth::AuthInterceptor;
+    use crate::financial_analysis::{MainApiClient, RegisterApiClient};
 
     #[test]
     fn client_native() {
-        tracing_subscriber::fmt::init();
+        let _ = tracing_subscriber::fmt::try_init();
         tokio::runtime::Runtime::new().unwrap().block_on(async move {
-            let addr = format!("http://127.0.0.1:{}", API_PORT);
+            let addr = mock_server::spawn(Default::default()).await.unwrap();
             //  why it did not generate `connect`?
-            let mut client: RegisterApiClient = rpc::api::register_client::RegisterClient::new(tonic::transport::Endpoint::new(addr).unwrap().connect().await.unwrap());
+            let channel = tonic::transport::Endpoint::new(format!("http://{}", addr)).unwrap().connect().await.unwrap();
+            let mut client: RegisterApiClient = rpc::api::register_client::RegisterClient::new(channel.clone());
             info!("got client: {:?}", client);
-            let _r = client.register(LoginRegisterRequest::default()).await.unwrap();
+            let user = LoginRegisterRequest { username: "test".to_string(), password: "test".to_string() };
+            let r = client.register(user.clone()).await.unwrap().into_inner();
+            assert!(!r.err, "{}", r.reason);
+            let token = Arc::new(RwLock::new("".to_string()));
+            let mut client: MainApiClient = rpc::api::api_rpc_client::ApiRpcClient::with_interceptor(channel, AuthInterceptor::new(token.clone()));
+            let r = client.login(user).await.unwrap().into_inner();
+            assert!(!r.err, "{}", r.reason);
+            *token.write().unwrap() = r.token;
+            let r = client.stock_list(StockListRequest { page_size: 10, ..Default::default() }).await.unwrap().into_inner();
+            assert_eq!(r.data.len(), 10);
+            assert!(!r.next_page_token.is_empty());
         });
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,134 @@
+use egui::util::History;
+use egui::{Color32, RichText, Ui};
+use crate::constants::{PING_HISTORY_SECONDS, PING_INTERVAL_SECONDS};
+use crate::financial_analysis::FinancialAnalysis;
+use crate::service::ServiceRequest;
+use crate::utils::now_millis;
+
+/// Results of periodic `Ping`, latency in milliseconds.
+pub struct Health {
+    latencies: History<f32>,
+    pub latency: Option<f32>,
+    pub reachable: Option<bool>,
+    pub error: String,
+    pub pinging: bool,
+    next_ping_at: f64,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        let max_age = PING_HISTORY_SECONDS as f32;
+        Self {
+            latencies: History::new(0..(max_age / PING_INTERVAL_SECONDS as f32).ceil() as usize + 1, max_age),
+            latency: None,
+            reachable: None,
+            error: "".to_string(),
+            pinging: false,
+            next_ping_at: 0.0,
+        }
+    }
+}
+
+impl Health {
+    pub fn on_pong(&mut self, latency: Option<f32>, error: String) {
+        self.pinging = false;
+        self.next_ping_at = now_millis() + PING_INTERVAL_SECONDS as f64 * 1e3;
+        self.reachable = Some(latency.is_some());
+        self.latency = latency;
+        self.error = error;
+        if let Some(latency) = latency {
+            self.latencies.add(now_millis() / 1e3, latency);
+        }
+    }
+    fn latency_color(latency: f32) -> Color32 {
+        if latency < 100.0 {
+            Color32::GREEN
+        } else if latency < 500.0 {
+            Color32::YELLOW
+        } else {
+            Color32::RED
+        }
+    }
+    pub fn status_ui(&self, ui: &mut Ui) {
+        match (self.reachable, self.latency) {
+            (Some(true), Some(latency)) => {
+                ui.label(RichText::new(format!("延迟 {:.0}ms", latency)).color(Self::latency_color(latency)))
+                    .on_hover_text(format!("平均 {:.0}ms", self.latencies.average().unwrap_or(latency)));
+            }
+            (Some(false), _) => {
+                ui.label(RichText::new("服务器不可达").color(ui.visuals().warn_fg_color))
+                    .on_hover_text(&self.error);
+            }
+            _ => {}
+        }
+    }
+    /// Latency history graph, like the cpu usage one in [`crate::frame_history::FrameHistory`].
+    pub fn graph(&self, ui: &mut Ui) -> egui::Response {
+        use egui::*;
+
+        let history = &self.latencies;
+        let height = ui.spacing().slider_width;
+        let size = vec2(ui.available_size_before_wrap().x, height);
+        let (rect, response) = ui.allocate_at_least(size, Sense::hover());
+        let style = ui.style().noninteractive();
+
+        let graph_top = history.values().fold(100.0f32, f32::max) * 1.2;
+        let graph_rect = Rect::from_x_y_ranges(history.max_age()..=0.0, graph_top..=0.0);
+        let to_screen = emath::RectTransform::from_to(graph_rect, rect);
+
+        let mut shapes = Vec::with_capacity(3 + 2 * history.len());
+        shapes.push(Shape::Rect(epaint::RectShape {
+            rect,
+            rounding: style.rounding,
+            fill: ui.visuals().extreme_bg_color,
+            stroke: ui.style().noninteractive().bg_stroke,
+        }));
+
+        let rect = rect.shrink(4.0);
+        let color = ui.visuals().text_color();
+        let line_stroke = Stroke::new(1.0, color);
+
+        if let Some(pointer_pos) = response.hover_pos() {
+            let y = pointer_pos.y;
+            shapes.push(Shape::line_segment(
+                [pos2(rect.left(), y), pos2(rect.right(), y)],
+                line_stroke,
+            ));
+            let latency = to_screen.inverse().transform_pos(pointer_pos).y;
+            shapes.push(ui.fonts(|f| {
+                Shape::text(
+                    f,
+                    pos2(rect.left(), y),
+                    Align2::LEFT_BOTTOM,
+                    format!("{:.0} ms", latency),
+                    TextStyle::Monospace.resolve(ui.style()),
+                    color,
+                )
+            }));
+        }
+
+        let right_side_time = now_millis() / 1e3;
+        let points: Vec<Pos2> = history.iter()
+            .map(|(time, latency)| to_screen.transform_pos_clamped(Pos2::new((right_side_time - time) as f32, latency)))
+            .collect();
+        shapes.push(Shape::line(points.clone(), line_stroke));
+        for (pos, (_, latency)) in points.into_iter().zip(history.iter()) {
+            shapes.push(Shape::circle_filled(pos, 2.0, Self::latency_color(latency)));
+        }
+
+        ui.painter().extend(shapes);
+
+        response
+    }
+}
+
+impl FinancialAnalysis {
+    /// Called every frame, pings the server periodically while connected.
+    pub fn check_health(&mut self) {
+        if self.health.pinging || !self.connected() || now_millis() < self.health.next_ping_at {
+            return;
+        }
+        self.health.pinging = true;
+        self.call(ServiceRequest::Ping);
+    }
+}
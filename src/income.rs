@@ -0,0 +1,149 @@
+use std::ops::RangeInclusive;
+use egui::{Align2, Color32, DragValue, Grid, Rect, RichText, Sense, Ui, vec2, Widget};
+use rpc::api::IncomeAnalysisResp;
+use crate::message::ViewRequestKind;
+use crate::stock_view::StockView;
+use crate::service::ViewRequest;
+use crate::utils::format_amount;
+
+pub const INCOME_YEARS_DEFAULT: u32 = 3;
+pub const INCOME_YEARS_MAX: u32 = 10;
+
+/// Growth rate of each year compared to the year before, `incomes` newest first.
+pub fn growth_rates(incomes: &[f32]) -> Vec<Option<f32>> {
+    (0..incomes.len())
+        .map(|i| match incomes.get(i + 1) {
+            Some(last) if *last > 0.0 => Some(incomes[i] / last - 1.0),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Compound annual growth rate from the oldest to the newest year, `incomes` newest first.
+pub fn cagr(incomes: &[f32]) -> Option<f32> {
+    let newest = *incomes.first()?;
+    let oldest = *incomes.last()?;
+    let n = incomes.len() - 1;
+    if n == 0 || oldest <= 0.0 || newest <= 0.0 {
+        return None;
+    }
+    Some((newest / oldest).powf(1.0 / n as f32) - 1.0)
+}
+
+fn rate_text(ui: &Ui, rate: Option<f32>) -> RichText {
+    match rate {
+        Some(r) => RichText::new(format!("{:+.2}%", r * 100.0))
+            .color(if r >= 0.0 { Color32::RED } else { Color32::GREEN }),
+        None => RichText::new("--").color(ui.visuals().weak_text_color()),
+    }
+}
+
+impl StockView {
+    pub fn request_income_analysis(&mut self) {
+        self.requesting_income_analysis = true;
+        self.income_analysis_error.clear();
+        self.call(ViewRequest::IncomeAnalysis((self.stock.code.to_string(), self.income_years)));
+    }
+    fn income_bar_chart(ui: &mut Ui, data: &IncomeAnalysisResp) {
+        let size = vec2(ui.available_width().clamp(120.0, 320.0), 80.0);
+        let (response, painter) = ui.allocate_painter(size, Sense::hover());
+        let rect = response.rect;
+        let len = data.incomes.len();
+        if len == 0 {
+            return;
+        }
+        let text_height = ui.text_style_height(&egui::TextStyle::Small);
+        let max = data.incomes.iter().cloned().fold(0.0f32, f32::max);
+        let width = rect.width() / len as f32;
+        let height = rect.height() - text_height;
+        // oldest year on the left
+        for (p, i) in (0..len).rev().enumerate() {
+            let value = data.incomes[i].max(0.0);
+            let left = rect.left() + p as f32 * width;
+            let top = if max > 0.0 { rect.top() + height * (1.0 - value / max) } else { rect.top() + height };
+            let bar = Rect::from_x_y_ranges(
+                RangeInclusive::new(left + width * 0.2, left + width * 0.8),
+                RangeInclusive::new(top, rect.top() + height));
+            painter.rect_filled(bar, 0.0, ui.visuals().selection.bg_fill);
+            let year = data.years.get(i).map(|x| x.as_str()).unwrap_or("-");
+            painter.text(bar.center_bottom(), Align2::CENTER_TOP, year,
+                         egui::TextStyle::Small.resolve(ui.style()), ui.visuals().text_color());
+            if let Some(pos) = response.hover_pos() {
+                if (left..=left + width).contains(&pos.x) {
+                    painter.text(pos, Align2::LEFT_BOTTOM, format_amount(data.incomes[i] as f64),
+                                 egui::TextStyle::Body.resolve(ui.style()), ui.visuals().strong_text_color());
+                }
+            }
+        }
+    }
+    pub fn income_analysis_ui(&mut self, ui: &mut Ui) {
+        ui.vertical(|ui| {
+            ui.add_enabled_ui(!self.requesting_income_analysis, |ui| {
+                ui.horizontal(|ui| {
+                    ui.strong("营业收入");
+                    DragValue::new(&mut self.income_years)
+                        .clamp_range(2..=INCOME_YEARS_MAX)
+                        .suffix("年")
+                        .ui(ui);
+                    if ui.button("分析").clicked() {
+                        self.request_income_analysis();
+                    }
+                    if self.requesting_income_analysis {
+                        ui.spinner();
+                    } else {
+                        self.data_age_ui(ui, ViewRequestKind::IncomeAnalysis);
+                    }
+                });
+            });
+            if !self.income_analysis_error.is_empty() {
+                ui.label(RichText::new(format!("加载营收数据失败：{}", self.income_analysis_error)).color(ui.visuals().warn_fg_color));
+            }
+            if let Some(income_analysis) = &self.income_analysis {
+                let growth = growth_rates(&income_analysis.incomes);
+                Grid::new(format!("{}-incomes-grid", self.stock.symbol))
+                    .num_columns(3)
+                    .spacing([40.0, 4.0])
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.strong("年份");
+                        ui.strong("营业收入");
+                        ui.strong("增长率");
+                        ui.end_row();
+                        for (i, income) in income_analysis.incomes.iter().enumerate() {
+                            ui.label(income_analysis.years.get(i).map(|x| x.as_str()).unwrap_or("-"));
+                            ui.label(format_amount(*income as f64));
+                            let rate = rate_text(ui, growth[i]);
+                            ui.label(rate);
+                            ui.end_row();
+                        }
+                        ui.strong("复合增长率");
+                        let rate = rate_text(ui, cagr(&income_analysis.incomes));
+                        ui.label(rate);
+                        ui.end_row();
+                    });
+                Self::income_bar_chart(ui, income_analysis);
+            } else if self.requesting_income_analysis {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("正在加载营收数据...");
+                });
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::income::{cagr, growth_rates};
+
+    #[test]
+    fn income_growth() {
+        let incomes = [121.0, 110.0, 100.0];
+        let growth = growth_rates(&incomes);
+        assert!((growth[0].unwrap() - 0.1).abs() < 1e-6);
+        assert!((growth[1].unwrap() - 0.1).abs() < 1e-6);
+        assert!(growth[2].is_none());
+        assert!((cagr(&incomes).unwrap() - 0.1).abs() < 1e-6);
+        assert!(cagr(&[100.0]).is_none());
+    }
+}
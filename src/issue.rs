@@ -1,5 +1,189 @@
+use egui::{Color32, Grid, RichText, Ui};
+use rpc::api::StockIssueResp;
 use crate::stock_view::StockView;
+use crate::utils::parse_value;
+
+/// Typed IPO issue details, amounts in 10k yuan and volumes in 10k shares as sent by the server.
+#[derive(Debug, Clone, Default)]
+pub struct StockIssue {
+    pub market: String,
+    pub consignee: String,
+    pub underwriting: String,
+    pub sponsor: String,
+    pub issue_mode: String,
+    pub issue_price: Option<f64>,
+    pub issue_pe: Option<f64>,
+    pub pre_capital: Option<f64>,
+    pub capital: Option<f64>,
+    pub issue_volume: Option<f64>,
+    pub expected_fundraising: Option<f64>,
+    pub fundraising: Option<f64>,
+    pub issue_cost: Option<f64>,
+    pub net_amount_raised: Option<f64>,
+    pub underwriting_fee: Option<f64>,
+    pub announcement_date: String,
+    pub launch_date: String,
+}
+
+impl From<&StockIssueResp> for StockIssue {
+    fn from(value: &StockIssueResp) -> Self {
+        Self {
+            market: value.market.to_string(),
+            consignee: value.consignee.to_string(),
+            underwriting: value.underwriting.to_string(),
+            sponsor: value.sponsor.to_string(),
+            issue_mode: value.issue_mode.to_string(),
+            issue_price: parse_value(&value.issue_price),
+            issue_pe: parse_value(&value.issue_pe),
+            pre_capital: parse_value(&value.pre_capital),
+            capital: parse_value(&value.capital),
+            issue_volume: parse_value(&value.issue_volume),
+            expected_fundraising: parse_value(&value.expected_fundraising),
+            fundraising: parse_value(&value.fundraising),
+            issue_cost: parse_value(&value.issue_cost),
+            net_amount_raised: parse_value(&value.net_amount_raised),
+            underwriting_fee: parse_value(&value.underwriting_fee),
+            announcement_date: value.announcement_date.to_string(),
+            launch_date: value.launch_date.to_string(),
+        }
+    }
+}
+
+fn ratio(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    let b = b.filter(|x| *x != 0.0)?;
+    Some(a? / b)
+}
+
+impl StockIssue {
+    /// Underwriting fee over total fundraising.
+    pub fn underwriting_fee_ratio(&self) -> Option<f64> {
+        ratio(self.underwriting_fee, self.fundraising)
+    }
+    /// Total issue cost over total fundraising.
+    pub fn issue_cost_ratio(&self) -> Option<f64> {
+        ratio(self.issue_cost, self.fundraising)
+    }
+    /// Actual fundraising over expected, minus one; positive when over-raised.
+    pub fn over_fundraising(&self) -> Option<f64> {
+        ratio(self.fundraising, self.expected_fundraising).map(|x| x - 1.0)
+    }
+    /// Newly issued shares over total shares after IPO.
+    pub fn issue_share_ratio(&self) -> Option<f64> {
+        ratio(self.issue_volume, self.capital)
+    }
+    /// Price return from issue price to `close`.
+    pub fn return_since_ipo(&self, close: f64) -> Option<f64> {
+        ratio(Some(close), self.issue_price).map(|x| x - 1.0)
+    }
+}
 
 impl StockView {
+    pub fn issue_metrics_ui(&self, ui: &mut Ui) {
+        let issue = match &self.issue_detail {
+            Some(issue) => issue,
+            None => return,
+        };
+        let percent = |x: Option<f64>| x.map(|x| format!("{:.2}%", x * 100.0)).unwrap_or_else(|| "--".to_string());
+        Grid::new(format!("{}-issue-metrics-grid", self.stock.symbol))
+            .num_columns(2)
+            .spacing([40.0, 4.0])
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong("发行指标");
+                ui.end_row();
+                ui.label("承销费率");
+                ui.label(percent(issue.underwriting_fee_ratio()));
+                ui.end_row();
+                ui.label("发行费用率");
+                ui.label(percent(issue.issue_cost_ratio()));
+                ui.end_row();
+                ui.label("超募比例");
+                ui.label(percent(issue.over_fundraising()));
+                ui.end_row();
+                ui.label("新股占比");
+                ui.label(percent(issue.issue_share_ratio()));
+                ui.end_row();
+                ui.label("上市以来涨幅");
+                let last = self.data.iter().rev().find(|x| x.valid());
+                match last.and_then(|x| issue.return_since_ipo(x.close as f64)) {
+                    Some(r) => {
+                        ui.label(RichText::new(format!("{:+.2}%", r * 100.0))
+                            .color(if r >= 0.0 { Color32::RED } else { Color32::GREEN }))
+                            .on_hover_text(format!("发行价 {:.2} → 收盘价 {:.2}（不复权）",
+                                                   issue.issue_price.unwrap_or_default(), last.map(|x| x.close).unwrap_or_default()));
+                    }
+                    None => { ui.label("--"); }
+                }
+                ui.end_row();
+            });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rpc::api::StockIssueResp;
+    use crate::issue::StockIssue;
+
+    fn resp() -> StockIssueResp {
+        StockIssueResp {
+            market: "上海证券交易所".to_string(),
+            issue_price: "10.00".to_string(),
+            issue_pe: "22.98".to_string(),
+            pre_capital: "30,000".to_string(),
+            capital: "40,000".to_string(),
+            issue_volume: "10000".to_string(),
+            expected_fundraising: "80000".to_string(),
+            fundraising: "100000".to_string(),
+            issue_cost: "8000".to_string(),
+            net_amount_raised: "92000".to_string(),
+            underwriting_fee: "6000".to_string(),
+            announcement_date: "2021-06-01".to_string(),
+            launch_date: "2021-06-18".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn assert_close(value: Option<f64>, expected: f64) {
+        assert!((value.unwrap() - expected).abs() < 1e-9, "{:?} {}", value, expected);
+    }
+
+    #[test]
+    fn issue_metrics() {
+        let issue = StockIssue::from(&resp());
+        assert_eq!(issue.market, "上海证券交易所");
+        assert_eq!(issue.issue_price, Some(10.0));
+        // thousands separators
+        assert_eq!(issue.pre_capital, Some(30000.0));
+        assert_eq!(issue.capital, Some(40000.0));
+        assert_eq!(issue.launch_date, "2021-06-18");
+        assert_close(issue.underwriting_fee_ratio(), 0.06);
+        assert_close(issue.issue_cost_ratio(), 0.08);
+        assert_close(issue.over_fundraising(), 0.25);
+        assert_close(issue.issue_share_ratio(), 0.25);
+        assert_close(issue.return_since_ipo(25.0), 1.5);
+        assert_close(issue.return_since_ipo(8.0), -0.2);
+    }
 
-}
\ No newline at end of file
+    #[test]
+    fn issue_missing_values() {
+        let issue = StockIssue::from(&StockIssueResp {
+            issue_price: "".to_string(),
+            underwriting_fee: "--".to_string(),
+            fundraising: "0".to_string(),
+            expected_fundraising: "--".to_string(),
+            ..resp()
+        });
+        assert_eq!(issue.issue_price, None);
+        assert_eq!(issue.underwriting_fee, None);
+        assert_eq!(issue.fundraising, Some(0.0));
+        assert_eq!(issue.underwriting_fee_ratio(), None);
+        // zero denominator
+        assert_eq!(issue.issue_cost_ratio(), None);
+        assert_eq!(issue.over_fundraising(), None);
+        assert_eq!(issue.return_since_ipo(25.0), None);
+        assert_close(issue.issue_share_ratio(), 0.25);
+        let empty = StockIssue::from(&StockIssueResp::default());
+        assert_eq!(empty.capital, None);
+        assert_eq!(empty.issue_share_ratio(), None);
+    }
+}
@@ -1,18 +1,34 @@
 #![warn(clippy::all, rust_2018_idioms)]
 
 pub mod app;
+pub mod auth;
+pub mod cache;
+pub mod connection;
 pub mod constants;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod credential;
 pub mod debug_panel;
 pub mod financial_analysis;
 pub mod frame_history;
+pub mod health;
 pub mod login;
+pub mod offline;
 pub mod password;
+pub mod profile;
+pub mod quotes;
+pub mod request;
 pub mod run_mode;
 pub mod utils;
 pub mod message;
+pub mod server_info;
 pub mod service;
+pub mod stock_search;
 pub mod stock_view;
+pub mod statements;
 pub mod issue;
+pub mod income;
+pub mod valuation;
+pub mod scoring;
 
 #[macro_use]
 extern crate rust_i18n;
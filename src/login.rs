@@ -1,15 +1,32 @@
 use crate::financial_analysis::FinancialAnalysis;
 use crate::password::password;
-use egui::{RichText, Window};
-use rpc::api::{LoginRegisterRequest, ReasonResp};
-use rpc::API_PORT;
-use tracing::{error, info};
-use crate::message::Message::{LoginDone, LoginError};
-use crate::utils::{block_on, execute};
+use egui::{Color32, RichText, Window};
+use rpc::api::LoginRegisterRequest;
+use crate::service::ServiceRequest;
+
+pub const USERNAME_LEN: std::ops::RangeInclusive<usize> = 3..=32;
+pub const PASSWORD_MIN_LEN: usize = 6;
+
+/// Check registration inputs before sending them to server.
+pub fn validate_register(username: &str, password: &str, confirm: &str) -> Result<(), String> {
+    if !USERNAME_LEN.contains(&username.chars().count()) {
+        return Err(format!("账户名长度需在 {} 到 {} 之间", USERNAME_LEN.start(), USERNAME_LEN.end()));
+    }
+    if !username.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err("账户名只能包含字母、数字和下划线".to_string());
+    }
+    if password.chars().count() < PASSWORD_MIN_LEN {
+        return Err(format!("密码长度不能少于 {} 位", PASSWORD_MIN_LEN));
+    }
+    if password != confirm {
+        return Err("两次输入的密码不一致".to_string());
+    }
+    Ok(())
+}
 
 impl FinancialAnalysis {
     pub fn login_window(&mut self, ctx: &egui::Context) {
-        Window::new("账户登录").show(ctx, |ui| {
+        Window::new(if self.register_mode { "账户注册" } else { "账户登录" }).show(ctx, |ui| {
             egui::Grid::new("login_grid")
                 .num_columns(2)
                 .spacing([40.0, 4.0])
@@ -20,71 +37,68 @@ impl FinancialAnalysis {
                     ui.end_row();
                     ui.label("密码");
                     ui.add(password(&mut self.input_password));
+                    if self.register_mode {
+                        ui.end_row();
+                        ui.label("确认密码");
+                        ui.add(password(&mut self.input_password_confirm));
+                    }
                 });
-            if !self.login_error.is_empty() {
+            if !self.register_mode {
+                #[cfg(not(target_arch = "wasm32"))]
+                self.credential_ui(ui);
+                #[cfg(target_arch = "wasm32")]
+                ui.checkbox(&mut self.profile_mut().remember, "记住我");
+            }
+            if !self.login_error.is_empty() && !self.register_mode {
                 ui.label(RichText::new(format!("登录失败：{}", self.login_error)).color(ui.visuals().warn_fg_color));
             }
+            if let Some((ok, message)) = &self.register_message {
+                ui.label(if *ok {
+                    RichText::new(message).color(Color32::GREEN)
+                } else {
+                    RichText::new(format!("注册失败：{}", message)).color(ui.visuals().warn_fg_color)
+                });
+            }
             ui.vertical_centered_justified(|ui| {
-                if ui.button("登录").clicked() {
-                    // block_on(self.login());
-                    if let Some(client) = &self.client {
-                        let mut client = client.clone();
-                        let username = self.input_username.clone();
-                        let password = self.input_password.clone();
-                        let tx = self.loop_tx.as_ref().map(|x| x.clone());
-                        execute(async move {
-                            info!("login, client: {:?}", client);
-                            let r = client.login(LoginRegisterRequest { username, password }).await;
-                            info!("login resp: {:?}", r);
-                            match r {
-                                Ok(r) => {
-                                    let data = r.into_inner();
-                                    if let Some(tx) = tx {
-                                        if data.err {
-                                            error!("{}", data.reason);
-                                            tx.send(LoginError(data.reason)).unwrap();
-                                        } else {
-                                            tx.send(LoginDone(data.token)).unwrap();
-                                        }
-                                    }
-                                }
-                                Err(e) => error!("{}", e.to_string())
-                            }
-                        });
+                if self.register_mode {
+                    ui.add_enabled_ui(!self.registering, |ui| {
+                        if ui.button(if self.registering { "正在注册..." } else { "注册" }).clicked() {
+                            self.register();
+                        }
+                    });
+                    if ui.button("返回登录").clicked() {
+                        self.register_mode = false;
+                        self.register_message = None;
                     }
+                    return;
+                }
+                if ui.button("登录").clicked() {
+                    self.call(ServiceRequest::Login(LoginRegisterRequest {
+                        username: self.input_username.clone(),
+                        password: self.input_password.clone(),
+                    }));
                 }
-                if ui.button("注册").clicked() {
-                    let username = self.input_username.to_string();
-                    let password = self.input_password.to_string();
-                    block_on(Self::register(username, password));
+                if ui.button("注册新账户").clicked() {
+                    self.register_mode = true;
+                    self.register_message = None;
                 }
             });
         });
     }
-    pub async fn register(username: String, password: String) {
-        let addr = format!("http://127.0.0.1:{}", API_PORT);
-        let res =
-            match if cfg!(target_arch = "wasm32") {
-                use tonic_web_wasm_client::Client;
-                let mut client = rpc::api::register_client::RegisterClient::new(Client::new(addr));
-                client.register(LoginRegisterRequest { username, password }).await
-            } else {
-                #[cfg(not(target_arch = "wasm32"))]
-                {
-                    let mut client = rpc::api::register_client::RegisterClient::new(tonic::transport::Endpoint::new(addr).unwrap().connect().await.unwrap());
-                    client.register(LoginRegisterRequest { username, password }).await
-                }
-                #[cfg(target_arch = "wasm32")]
-                {
-                    panic!("unsupported")
-                }
-            } {
-                Ok(r) => r.into_inner(),
-                Err(e) => {
-                    error!("{}", e);
-                    ReasonResp { err: true, reason: e.to_string() }
-                }
-            };
-        info!("register resp: {:?}", res);
+    pub fn register(&mut self) {
+        if let Err(e) = validate_register(&self.input_username, &self.input_password, &self.input_password_confirm) {
+            self.register_message = Some((false, e));
+            return;
+        }
+        if !self.connected() {
+            self.register_message = Some((false, "未连接到服务器".to_string()));
+            return;
+        }
+        self.registering = true;
+        self.register_message = None;
+        self.call(ServiceRequest::Register(LoginRegisterRequest {
+            username: self.input_username.to_string(),
+            password: self.input_password.to_string(),
+        }));
     }
 }
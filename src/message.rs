@@ -1,25 +1,100 @@
-use std::sync::mpsc;
-use rpc::api::{GuideLineResp, IncomeAnalysisResp, StockIssueResp, StockListResp, TradingHistoryItem};
-use crate::financial_analysis::{MainApiClient, Token};
+use rpc::api::{BalanceSheetResp, ServerInfoResp, ReasonResp, CashFlowStatementResp, GuideLineResp, IncomeAnalysisResp, IncomeStatementResp, Quote, StockIssueResp, StockListRequest, StockListResp, TradingHistoryItem};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::credential::Credentials;
+use crate::financial_analysis::Token;
 use crate::stock_view::TradingHistoryValueItem;
 
 #[derive(Debug)]
 pub enum Message {
-    ApiClientConnect(MainApiClient),
+    /// service connected, with the generation of connect request
+    ApiClientConnect(u64),
+    ConnectFailed((u64, String)),
+    /// server unreachable while connected
+    ConnectionLost(String),
+    /// (round-trip latency in ms, error) of a ping
+    Pong((Option<f32>, String)),
+    GotServerInfo(ServerInfoResp),
     LoginDone(Token),
     LoginError(String),
-    GotStockList(StockListResp),
-    GotTradingHistory((String, Vec<TradingHistoryItem>, String)),
-    GotPredicts((String, Vec<TradingHistoryValueItem>, String)),
-    GotStockIssue((String, StockIssueResp, String)),
-    GotGuideLine((String, GuideLineResp, String)),
-    GotIncomeAnalysis((String, IncomeAnalysisResp)),
+    RegisterDone(ReasonResp),
+    TokenRefreshed(Token),
+    TokenRefreshError(String),
+    /// server rejected current session token
+    Unauthenticated(String),
+    /// (generation, request, list, time fetched from server)
+    GotStockList((u64, StockListRequest, StockListResp, u64)),
+    /// (generation, error)
+    StockListError((u64, String)),
+    GotQuote(Quote),
+    /// (tag, response, time fetched from server), response to a request of one stock view
+    ViewResponse((RequestTag, ViewResponse, u64)),
+    #[cfg(not(target_arch = "wasm32"))]
+    CredentialsUnlocked(Credentials),
+    #[cfg(not(target_arch = "wasm32"))]
+    CredentialsSaved,
+    #[cfg(not(target_arch = "wasm32"))]
+    CredentialError(String),
 }
 
-unsafe impl Send for Message {}
+pub type ViewId = u64;
+pub type RequestId = u64;
+
+/// Identifies the view a response belongs to and the request it answers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RequestTag {
+    pub view: ViewId,
+    pub request: RequestId,
+}
+
+/// Kind of view request, a view only accepts the latest response of each kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ViewRequestKind {
+    TradingHistory,
+    Predict,
+    StockIssue,
+    GuideLine,
+    IncomeAnalysis,
+    Statements,
+}
 
 #[derive(Debug)]
-pub struct Channel {
-    pub tx: mpsc::Sender<Message>,
-    pub rx: mpsc::Receiver<Message>,
+pub enum ViewResponse {
+    /// (since, bars, error), bars after `since` to merge, or the whole history when it's empty
+    TradingHistory((String, Vec<TradingHistoryItem>, String)),
+    Predicts((Vec<TradingHistoryValueItem>, String)),
+    StockIssue((StockIssueResp, String)),
+    GuideLine((GuideLineResp, String)),
+    IncomeAnalysis((IncomeAnalysisResp, String)),
+    IncomeStatement((IncomeStatementResp, String)),
+    BalanceSheet((BalanceSheetResp, String)),
+    CashFlowStatement((CashFlowStatementResp, String)),
 }
+
+impl ViewResponse {
+    pub fn kind(&self) -> ViewRequestKind {
+        match self {
+            ViewResponse::TradingHistory(_) => ViewRequestKind::TradingHistory,
+            ViewResponse::Predicts(_) => ViewRequestKind::Predict,
+            ViewResponse::StockIssue(_) => ViewRequestKind::StockIssue,
+            ViewResponse::GuideLine(_) => ViewRequestKind::GuideLine,
+            ViewResponse::IncomeAnalysis(_) => ViewRequestKind::IncomeAnalysis,
+            ViewResponse::IncomeStatement(_)
+            | ViewResponse::BalanceSheet(_)
+            | ViewResponse::CashFlowStatement(_) => ViewRequestKind::Statements,
+        }
+    }
+    pub fn error(&self) -> &str {
+        match self {
+            ViewResponse::TradingHistory((_, _, e))
+            | ViewResponse::Predicts((_, e))
+            | ViewResponse::StockIssue((_, e))
+            | ViewResponse::GuideLine((_, e))
+            | ViewResponse::IncomeAnalysis((_, e))
+            | ViewResponse::IncomeStatement((_, e))
+            | ViewResponse::BalanceSheet((_, e))
+            | ViewResponse::CashFlowStatement((_, e)) => e,
+        }
+    }
+}
+
+unsafe impl Send for Message {}
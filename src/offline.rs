@@ -0,0 +1,53 @@
+use egui::{Color32, RichText, Ui};
+use tracing::info;
+use crate::financial_analysis::FinancialAnalysis;
+use crate::utils::{format_duration, now_seconds};
+
+/// Data older than this is highlighted.
+const STALE_SECONDS: u64 = 86400;
+
+/// "updated x ago" label of data fetched at `fetched_at`, nothing for data never fetched.
+pub fn data_age_ui(ui: &mut Ui, fetched_at: Option<u64>) {
+    let Some(fetched_at) = fetched_at else { return; };
+    let age = now_seconds().saturating_sub(fetched_at);
+    let text = RichText::new(format!("更新于 {} 前", format_duration(age))).small();
+    let text = if age > STALE_SECONDS {
+        text.color(ui.visuals().warn_fg_color)
+    } else {
+        text.color(ui.visuals().weak_text_color())
+    };
+    ui.label(text).on_hover_text("数据获取时间");
+}
+
+impl FinancialAnalysis {
+    /// Browse cached data without a server, nothing can be changed or computed.
+    pub fn enter_offline(&mut self) {
+        info!("entering offline mode");
+        self.offline = true;
+        self.stock_list_error.clear();
+        for view in &mut self.history_views {
+            view.offline = true;
+        }
+        // server side search results are filtered locally from here on
+        if self.search.server_side {
+            self.load_stock_list();
+        }
+    }
+    pub fn leave_offline(&mut self) {
+        info!("leaving offline mode");
+        self.offline = false;
+        for view in &mut self.history_views {
+            view.offline = false;
+        }
+    }
+    pub fn offline_ui(&mut self, ui: &mut Ui) {
+        if !self.offline {
+            return;
+        }
+        ui.label(RichText::new("● 离线只读模式").color(Color32::LIGHT_BLUE))
+            .on_hover_text("显示的是本地缓存的数据");
+        if ui.button("退出离线模式").clicked() {
+            self.leave_offline();
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use egui::{Checkbox, CollapsingHeader, DragValue, Grid, RichText, TextEdit, Window};
+use rpc::API_PORT;
+use crate::financial_analysis::{FinancialAnalysis, Token};
+#[cfg(not(target_arch = "wasm32"))]
+use anyhow::Context;
+#[cfg(not(target_arch = "wasm32"))]
+use tonic::transport::{Certificate, ClientTlsConfig, Endpoint, Identity};
+
+/// A named server connection and the account logged in on it.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct ConnectionProfile {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    pub tls: bool,
+    /// PEM file of extra CA certificate, native only
+    pub ca_cert: String,
+    /// PEM files of client certificate and its key for mutual TLS, native only
+    pub client_cert: String,
+    pub client_key: String,
+    pub username: String,
+    /// keep token across restarts, in the encrypted credential file on native
+    pub remember: bool,
+    #[cfg_attr(not(target_arch = "wasm32"), serde(skip))]
+    pub token: Token,
+}
+
+impl Default for ConnectionProfile {
+    fn default() -> Self {
+        Self {
+            name: "本地".to_string(),
+            host: "localhost".to_string(),
+            port: API_PORT,
+            tls: false,
+            ca_cert: "".to_string(),
+            client_cert: "".to_string(),
+            client_key: "".to_string(),
+            username: "".to_string(),
+            remember: false,
+            token: "".to_string(),
+        }
+    }
+}
+
+impl ConnectionProfile {
+    pub fn new(name: &str, host: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            host: host.to_string(),
+            ..Default::default()
+        }
+    }
+    pub fn defaults() -> Vec<Self> {
+        vec![
+            Self::new("本地", "localhost"),
+            Self::new("远程", "a.chiro.work"),
+        ]
+    }
+    pub fn url(&self) -> String {
+        format!("{}://{}:{}", if self.tls { "https" } else { "http" }, self.host, self.port)
+    }
+    /// Endpoint of this profile, with TLS configured from the certificate files.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn endpoint(&self) -> anyhow::Result<Endpoint> {
+        let endpoint = Endpoint::new(self.url())?;
+        if !self.tls {
+            return Ok(endpoint);
+        }
+        let mut config = ClientTlsConfig::new().domain_name(&self.host);
+        if !self.ca_cert.is_empty() {
+            let pem = std::fs::read(&self.ca_cert).with_context(|| format!("读取 CA 证书 {} 失败", self.ca_cert))?;
+            config = config.ca_certificate(Certificate::from_pem(pem));
+        }
+        if !self.client_cert.is_empty() {
+            let cert = std::fs::read(&self.client_cert).with_context(|| format!("读取客户端证书 {} 失败", self.client_cert))?;
+            let key = std::fs::read(&self.client_key).with_context(|| format!("读取客户端私钥 {} 失败", self.client_key))?;
+            config = config.identity(Identity::from_pem(cert, key));
+        }
+        Ok(endpoint.tls_config(config)?)
+    }
+}
+
+impl FinancialAnalysis {
+    pub fn profile(&self) -> &ConnectionProfile {
+        &self.profiles[self.profile_index]
+    }
+    pub fn profile_mut(&mut self) -> &mut ConnectionProfile {
+        &mut self.profiles[self.profile_index]
+    }
+    /// Make sure there is at least one profile and the index is valid.
+    ///
+    /// Host and token of states saved before profiles go to the profile of that host,
+    /// or the first one, only once as they are not saved again.
+    pub fn fix_profiles(&mut self) {
+        if self.profiles.is_empty() {
+            self.profiles = ConnectionProfile::defaults();
+        }
+        if self.profile_index >= self.profiles.len() {
+            self.profile_index = 0;
+        }
+        if let Some(host) = self.api_host.take().filter(|x| !x.is_empty()) {
+            let index = self.profiles.iter().position(|x| x.host == host).unwrap_or(0);
+            self.profiles[index].host = host;
+            self.profile_index = index;
+        }
+        if let Some(token) = self.legacy_token.take().filter(|x| !x.is_empty()) {
+            self.profile_mut().token = token;
+        }
+    }
+    /// Switch to another profile, restoring its session and reconnecting.
+    pub fn switch_profile(&mut self, index: usize) {
+        if index >= self.profiles.len() || index == self.profile_index {
+            return;
+        }
+        self.profile_index = index;
+        self.stock_list.clear();
+        self.stock_list_select.clear();
+        self.stock_list_select_text.clear();
+        self.stock_list_popular.clear();
+        self.stock_list_time = None;
+        self.stock_list_error.clear();
+        self.search.reset();
+        self.history_views.clear();
+        self.login_error.clear();
+        self.restore_session();
+        self.refresh_client();
+    }
+    pub fn profiles_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.show_profiles_window;
+        let mut switch_to = None;
+        let mut remove = None;
+        Window::new("服务器配置")
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let removable = self.profiles.len() > 1;
+                for (i, profile) in self.profiles.iter_mut().enumerate() {
+                    let active = i == self.profile_index;
+                    let title = if active { format!("{} (当前)", profile.name) } else { profile.name.to_string() };
+                    CollapsingHeader::new(title)
+                        .id_source(format!("profile-{}", i))
+                        .default_open(active)
+                        .show(ui, |ui| {
+                            Grid::new(format!("profile-grid-{}", i))
+                                .num_columns(2)
+                                .spacing([40.0, 4.0])
+                                .striped(true)
+                                .show(ui, |ui| {
+                                    ui.label("名称");
+                                    ui.text_edit_singleline(&mut profile.name);
+                                    ui.end_row();
+                                    // the client and the session are built from the connection of the active profile
+                                    ui.label("地址");
+                                    ui.add_enabled(!active, TextEdit::singleline(&mut profile.host));
+                                    ui.end_row();
+                                    ui.label("端口");
+                                    ui.add_enabled(!active, DragValue::new(&mut profile.port));
+                                    ui.end_row();
+                                    ui.label("TLS");
+                                    ui.add_enabled(!active, Checkbox::new(&mut profile.tls, "使用 https"));
+                                    ui.end_row();
+                                    #[cfg(not(target_arch = "wasm32"))]
+                                    if profile.tls {
+                                        ui.label("CA 证书");
+                                        ui.add_enabled(!active, TextEdit::singleline(&mut profile.ca_cert))
+                                            .on_hover_text("PEM 文件路径，留空使用系统证书");
+                                        ui.end_row();
+                                        ui.label("客户端证书");
+                                        ui.add_enabled(!active, TextEdit::singleline(&mut profile.client_cert))
+                                            .on_hover_text("PEM 文件路径，留空不使用客户端证书");
+                                        ui.end_row();
+                                        ui.label("客户端私钥");
+                                        ui.add_enabled(!active, TextEdit::singleline(&mut profile.client_key));
+                                        ui.end_row();
+                                    }
+                                    ui.label("账户名");
+                                    ui.label(if profile.username.is_empty() { "未登录" } else { profile.username.as_str() });
+                                    ui.end_row();
+                                    ui.label("记住登录");
+                                    ui.label(if profile.remember { "是" } else { "否" });
+                                    ui.end_row();
+                                });
+                            ui.horizontal(|ui| {
+                                if active {
+                                    ui.label(RichText::new("切换到其他配置后才能修改连接参数").color(ui.visuals().weak_text_color()));
+                                } else if ui.button("切换到此配置").clicked() {
+                                    switch_to = Some(i);
+                                }
+                                ui.add_enabled_ui(removable && !active, |ui| {
+                                    if ui.button("删除").clicked() {
+                                        remove = Some(i);
+                                    }
+                                });
+                            });
+                        });
+                }
+                if ui.button("新建配置").clicked() {
+                    self.profiles.push(ConnectionProfile::new(&format!("配置{}", self.profiles.len() + 1), "localhost"));
+                }
+            });
+        self.show_profiles_window = open;
+        if let Some(i) = remove {
+            self.profiles.remove(i);
+            if i < self.profile_index {
+                self.profile_index -= 1;
+            }
+        }
+        if let Some(i) = switch_to {
+            self.switch_profile(i);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::financial_analysis::FinancialAnalysis;
+
+    #[test]
+    fn migrate_legacy_state() {
+        let mut app: FinancialAnalysis = serde_json::from_str(r#"{"api_host":"a.chiro.work","token":"legacy-token"}"#).unwrap();
+        app.fix_profiles();
+        assert_eq!(app.profile_index, 1);
+        assert_eq!(app.profile().token, "legacy-token");
+        assert!(app.api_host.is_none() && app.legacy_token.is_none());
+        let text = serde_json::to_string(&app).unwrap();
+        assert!(!text.contains("api_host") && !text.contains("legacy-token"));
+        // unknown hosts replace the first profile
+        let mut app: FinancialAnalysis = serde_json::from_str(r#"{"api_host":"10.0.0.2"}"#).unwrap();
+        app.fix_profiles();
+        assert_eq!((app.profile_index, app.profile().host.as_str()), (0, "10.0.0.2"));
+    }
+}
@@ -0,0 +1,151 @@
+use egui::{Color32, RichText};
+use rpc::api::{Quote, TradingHistoryType};
+use crate::constants::QUOTE_FLASH_SECONDS;
+use crate::financial_analysis::FinancialAnalysis;
+use crate::server_info::FEATURE_QUOTES;
+use crate::service::ServiceRequest;
+use crate::stock_view::{StockView, TradingHistoryValueItem};
+use crate::utils::{now_millis, parse_days};
+
+impl FinancialAnalysis {
+    /// Called every frame, keeps quotes subscribed for symbols of open views.
+    pub fn check_quote_subscription(&mut self) {
+        // legacy servers only answer it with Unimplemented
+        let mut symbols: Vec<String> = if self.server_info.advertises(FEATURE_QUOTES) {
+            self.history_views.iter().filter(|x| x.valid).map(|x| x.stock.symbol.to_string()).collect()
+        } else {
+            vec![]
+        };
+        symbols.sort();
+        symbols.dedup();
+        if symbols != self.quote_symbols {
+            self.quote_symbols = symbols.clone();
+            self.call(ServiceRequest::SubscribeQuotes(symbols));
+        }
+    }
+    pub fn on_quote(&mut self, quote: Quote) {
+        for view in self.history_views.iter_mut().filter(|x| x.stock.symbol == quote.symbol) {
+            view.on_quote(quote.clone());
+        }
+    }
+}
+
+/// Index of the bar period `date` falls in, weeks start on Monday.
+fn period(date: &str, typ: TradingHistoryType) -> Option<i64> {
+    match typ {
+        TradingHistoryType::Daily => parse_days(date),
+        // 1970-01-01 was a Thursday
+        TradingHistoryType::Week => parse_days(date).map(|x| (x + 3).div_euclid(7)),
+        TradingHistoryType::Month => {
+            let year: i64 = date.get(0..4)?.parse().ok()?;
+            let month: i64 = date.get(5..7)?.parse().ok()?;
+            Some(year * 12 + month)
+        }
+    }
+}
+
+impl StockView {
+    pub fn on_quote(&mut self, quote: Quote) {
+        if self.quote.as_ref().map(|x| x.timestamp) == Some(quote.timestamp) {
+            // polled again without change
+            return;
+        }
+        self.quote_updated_at = now_millis();
+        self.update_last_bar(&quote);
+        self.quote = Some(quote);
+    }
+    fn update_last_bar(&mut self, quote: &Quote) {
+        let Some(last) = self.data.last_mut() else { return; };
+        if quote.date < last.date {
+            return;
+        }
+        let new_period = match (period(&quote.date, self.typ), period(&last.date, self.typ)) {
+            (Some(current), Some(last)) => current > last,
+            // can't tell which bar it belongs to
+            _ => return,
+        };
+        let daily = self.typ == TradingHistoryType::Daily;
+        if new_period {
+            self.data.push(TradingHistoryValueItem {
+                date: quote.date.to_string(),
+                open: quote.open,
+                close: quote.price,
+                high: quote.high,
+                low: quote.low,
+                volume: quote.volume as usize,
+            });
+        } else {
+            // weekly and monthly bars take in the day, volume of the period is unknown
+            last.high = last.high.max(quote.high);
+            last.low = last.low.min(quote.low);
+            last.close = quote.price;
+            if daily {
+                last.open = quote.open;
+                last.volume = quote.volume as usize;
+            }
+        }
+    }
+    pub fn flashing(&self) -> bool {
+        now_millis() - self.quote_updated_at < QUOTE_FLASH_SECONDS * 1e3
+    }
+    /// Window title with last price and change, flashes on updates.
+    pub fn title(&self) -> RichText {
+        let title = format!("[{}]{}", self.stock.code, self.stock.name);
+        let Some(quote) = &self.quote else { return RichText::new(title); };
+        let change = if quote.pre_close > 0.0 { (quote.price - quote.pre_close) / quote.pre_close * 100.0 } else { 0.0 };
+        let text = RichText::new(format!("{}  {:.2} {:+.2}%", title, quote.price, change));
+        let color = if change > 0.0 {
+            Some(Color32::RED)
+        } else if change < 0.0 {
+            Some(Color32::GREEN)
+        } else {
+            None
+        };
+        match (self.flashing(), color) {
+            (true, color) => text.color(Color32::WHITE).background_color(color.unwrap_or(Color32::GRAY).linear_multiply(0.6)),
+            (false, Some(color)) => text.color(color),
+            (false, None) => text,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rpc::api::{Quote, StockResp, TradingHistoryType};
+    use crate::stock_view::{StockView, TradingHistoryValueItem};
+
+    #[test]
+    fn quote_periods() {
+        let bar = |date: &str| TradingHistoryValueItem { date: date.to_string(), open: 10.0, close: 10.0, high: 10.0, low: 10.0, volume: 100 };
+        let quote = |date: &str, timestamp: i64| Quote { date: date.to_string(), open: 11.0, high: 12.0, low: 9.0, price: 11.5, volume: 50, timestamp, ..Default::default() };
+        // (timeframe, last bar, quote date, bars after, close of last bar)
+        let cases = [
+            (TradingHistoryType::Daily, "2023-06-29", "2023-06-29", 1, 11.5),
+            (TradingHistoryType::Daily, "2023-06-29", "2023-06-30", 2, 11.5),
+            // Monday and Friday of the same week
+            (TradingHistoryType::Week, "2023-06-26", "2023-06-30", 1, 11.5),
+            (TradingHistoryType::Week, "2023-06-30", "2023-07-03", 2, 11.5),
+            (TradingHistoryType::Month, "2023-06-01", "2023-06-30", 1, 11.5),
+            (TradingHistoryType::Month, "2023-06-30", "2023-07-03", 2, 11.5),
+            // older quotes are ignored
+            (TradingHistoryType::Daily, "2023-06-30", "2023-06-29", 1, 10.0),
+        ];
+        for (i, (typ, last, date, len, close)) in cases.into_iter().enumerate() {
+            let mut view = StockView::new(1, StockResp::default(), None);
+            view.typ = typ;
+            view.data = vec![bar(last)];
+            view.on_quote(quote(date, i as i64));
+            assert_eq!((view.data.len(), view.data.last().unwrap().close), (len, close), "{:?} {} {}", typ, last, date);
+            if len == 2 {
+                assert_eq!(view.data[1].date, date);
+                assert_eq!((view.data[1].open, view.data[1].volume), (11.0, 50));
+            }
+        }
+        // a weekly bar keeps its open and volume
+        let mut view = StockView::new(1, StockResp::default(), None);
+        view.typ = TradingHistoryType::Week;
+        view.data = vec![bar("2023-06-26")];
+        view.on_quote(quote("2023-06-28", 1));
+        assert_eq!((view.data[0].open, view.data[0].high, view.data[0].low, view.data[0].volume), (10.0, 12.0, 9.0, 100));
+    }
+}
@@ -0,0 +1,87 @@
+use std::future::Future;
+use std::time::Duration;
+use egui::{DragValue, Grid, Ui, Widget};
+use futures::channel::oneshot;
+use futures::future::{select, Either, Shared};
+use futures::FutureExt;
+use tonic::Request;
+
+/// Per-RPC timeouts in seconds, `0` means no deadline.
+///
+/// Sent as `grpc-timeout` metadata, so the server gives up as well; the native
+/// channel also enforces it on our side.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct RpcTimeouts {
+    pub ping: u64,
+    pub auth: u64,
+    pub stock_list: u64,
+    pub trading_history: u64,
+    pub predict: u64,
+    pub financial: u64,
+}
+
+impl Default for RpcTimeouts {
+    fn default() -> Self {
+        Self {
+            ping: 5,
+            auth: 10,
+            stock_list: 30,
+            trading_history: 20,
+            predict: 120,
+            financial: 20,
+        }
+    }
+}
+
+impl RpcTimeouts {
+    pub fn ui(&mut self, ui: &mut Ui) {
+        Grid::new("rpc-timeouts")
+            .num_columns(2)
+            .show(ui, |ui| {
+                for (label, value) in [
+                    ("心跳检测", &mut self.ping),
+                    ("登录认证", &mut self.auth),
+                    ("股票列表", &mut self.stock_list),
+                    ("K 线", &mut self.trading_history),
+                    ("预测", &mut self.predict),
+                    ("财务数据", &mut self.financial),
+                ] {
+                    ui.label(label);
+                    DragValue::new(value).clamp_range(0..=600).suffix("s").ui(ui);
+                    ui.end_row();
+                }
+            });
+    }
+}
+
+/// Wrap a message into a request with deadline of `seconds`.
+pub fn with_timeout<T>(message: T, seconds: u64) -> Request<T> {
+    let mut request = Request::new(message);
+    if seconds > 0 {
+        request.set_timeout(Duration::from_secs(seconds));
+    }
+    request
+}
+
+/// Held by the owner of requests, dropping it cancels every linked [`CancelToken`].
+#[derive(Debug)]
+pub struct CancelGuard(#[allow(dead_code)] oneshot::Sender<()>);
+
+#[derive(Debug, Clone)]
+pub struct CancelToken(Shared<oneshot::Receiver<()>>);
+
+pub fn cancel_pair() -> (CancelGuard, CancelToken) {
+    let (tx, rx) = oneshot::channel();
+    (CancelGuard(tx), CancelToken(rx.shared()))
+}
+
+impl CancelToken {
+    /// Run `f` until it finishes, or drop it once the guard is gone.
+    pub async fn run<F: Future>(self, f: F) -> Option<F::Output> {
+        match select(Box::pin(f), self.0).await {
+            Either::Left((output, _)) => Some(output),
+            Either::Right(_) => None,
+        }
+    }
+}
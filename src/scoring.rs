@@ -0,0 +1,340 @@
+use egui::{Color32, Grid, RichText, Ui};
+use rpc::api::{BalanceSheetItem, CashFlowStatementItem, IncomeStatementItem};
+use crate::statements::Statement;
+use crate::message::ViewRequestKind;
+use crate::stock_view::StockView;
+use crate::utils::{format_amount, parse_value};
+
+/// One contributing signal of a score, `passed` is `None` when data is missing.
+#[derive(Debug, Clone)]
+pub struct Signal {
+    pub name: &'static str,
+    pub passed: Option<bool>,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct FScore {
+    pub year: i32,
+    pub signals: Vec<Signal>,
+}
+
+impl FScore {
+    pub fn score(&self) -> usize {
+        self.signals.iter().filter(|x| x.passed == Some(true)).count()
+    }
+    pub fn available(&self) -> usize {
+        self.signals.iter().filter(|x| x.passed.is_some()).count()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ZScore {
+    pub date: String,
+    /// (name, value, weight)
+    pub components: Vec<(&'static str, Option<f64>, f64)>,
+    /// X4 uses market value of equity, otherwise book value
+    pub market_value: bool,
+}
+
+impl ZScore {
+    pub fn z(&self) -> Option<f64> {
+        self.components.iter().map(|(_, v, w)| v.map(|v| v * w)).sum()
+    }
+    pub fn zone(z: f64) -> (&'static str, Color32) {
+        if z > 2.99 {
+            ("安全区", Color32::GREEN)
+        } else if z >= 1.81 {
+            ("灰色区", Color32::YELLOW)
+        } else {
+            ("危险区", Color32::RED)
+        }
+    }
+}
+
+fn div(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    let b = b?;
+    if b == 0.0 { None } else { Some(a? / b) }
+}
+
+fn sub(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    Some(a? - b?)
+}
+
+fn annual<T: Statement>(data: &[T], year: i32) -> Option<&T> {
+    let date = format!("{}-12-31", year);
+    data.iter().find(|x| x.date() == date)
+}
+
+fn latest_annual_year<T: Statement>(data: &[T]) -> Option<i32> {
+    data.iter()
+        .filter(|x| x.date().ends_with("12-31"))
+        .filter_map(|x| x.date().get(0..4)?.parse().ok())
+        .max()
+}
+
+fn amount(x: Option<f64>) -> String {
+    x.map(format_amount).unwrap_or_else(|| "--".to_string())
+}
+
+fn signal(name: &'static str, passed: Option<bool>, detail: String) -> Signal {
+    Signal { name, passed, detail }
+}
+
+fn compare(name: &'static str, current: Option<f64>, last: Option<f64>, higher_better: bool) -> Signal {
+    let passed = current.zip(last).map(|(c, l)| if higher_better { c > l } else { c < l });
+    let text = |x: Option<f64>| x.map(|x| format!("{:.4}", x)).unwrap_or_else(|| "--".to_string());
+    signal(name, passed, format!("{} → {}", text(last), text(current)))
+}
+
+/// Piotroski F-score of the latest annual report, compared with the year before.
+pub fn piotroski(income: &[IncomeStatementItem], balance: &[BalanceSheetItem], cash_flow: &[CashFlowStatementItem]) -> Option<FScore> {
+    let year = latest_annual_year(income)?;
+    let (i1, i0) = (annual(income, year)?, annual(income, year - 1)?);
+    let (b1, b0) = (annual(balance, year)?, annual(balance, year - 1)?);
+    let c1 = annual(cash_flow, year);
+    let v = |x: &String| parse_value(x);
+    let roa = |i: &IncomeStatementItem, b: &BalanceSheetItem| div(v(&i.net_profit), v(&b.total_assets));
+    let cfo = c1.and_then(|c| v(&c.net_operating_cash_flow));
+    let leverage = |b: &BalanceSheetItem| div(v(&b.long_term_loans).or(Some(0.0)), v(&b.total_assets));
+    let current_ratio = |b: &BalanceSheetItem| div(v(&b.current_assets), v(&b.current_liabilities));
+    let gross_margin = |i: &IncomeStatementItem| div(sub(v(&i.revenue), v(&i.operating_cost)), v(&i.revenue));
+    let turnover = |i: &IncomeStatementItem, b: &BalanceSheetItem| div(v(&i.revenue), v(&b.total_assets));
+    let net_profit = v(&i1.net_profit);
+    let signals = vec![
+        signal("ROA > 0", roa(i1, b1).map(|x| x > 0.0),
+               roa(i1, b1).map(|x| format!("{:.4}", x)).unwrap_or_default()),
+        signal("经营现金流 > 0", cfo.map(|x| x > 0.0),
+               amount(cfo)),
+        compare("ROA 提升", roa(i1, b1), roa(i0, b0), true),
+        signal("经营现金流 > 净利润", cfo.zip(net_profit).map(|(c, n)| c > n),
+               format!("{} / {}", amount(cfo), amount(net_profit))),
+        compare("长期杠杆下降", leverage(b1), leverage(b0), false),
+        compare("流动比率提升", current_ratio(b1), current_ratio(b0), true),
+        {
+            let (shares1, shares0) = (v(&b1.paid_in_capital), v(&b0.paid_in_capital));
+            signal("未增发新股", shares1.zip(shares0).map(|(s1, s0)| s1 <= s0),
+                   format!("{} → {}", amount(shares0), amount(shares1)))
+        },
+        compare("毛利率提升", gross_margin(i1), gross_margin(i0), true),
+        compare("资产周转率提升", turnover(i1, b1), turnover(i0, b0), true),
+    ];
+    Some(FScore { year, signals })
+}
+
+/// Altman Z-score of the latest balance sheet, `market_cap` replaces book equity in X4 when known.
+pub fn altman(income: &[IncomeStatementItem], balance: &[BalanceSheetItem], market_cap: Option<f64>) -> Option<ZScore> {
+    let year = latest_annual_year(income)?;
+    let i = annual(income, year)?;
+    let b = annual(balance, year)?;
+    let v = |x: &String| parse_value(x);
+    let total_assets = v(&b.total_assets);
+    let ebit = Some(v(&i.total_profit)? + v(&i.financial_expenses).unwrap_or(0.0));
+    let equity = market_cap.or_else(|| v(&b.total_equity));
+    Some(ZScore {
+        date: b.date.to_string(),
+        components: vec![
+            ("X1 营运资本 / 总资产", div(sub(v(&b.current_assets), v(&b.current_liabilities)), total_assets), 1.2),
+            ("X2 未分配利润 / 总资产", div(v(&b.retained_earnings), total_assets), 1.4),
+            ("X3 息税前利润 / 总资产", div(ebit, total_assets), 3.3),
+            ("X4 股权价值 / 总负债", div(equity, v(&b.total_liabilities)), 0.6),
+            ("X5 营业收入 / 总资产", div(v(&i.revenue), total_assets), 1.0),
+        ],
+        market_value: market_cap.is_some(),
+    })
+}
+
+impl StockView {
+    pub fn scoring_ui(&mut self, ui: &mut Ui) {
+        if !self.requesting_statements && self.income_statement.is_none() {
+            self.request_statements(true);
+        }
+        // share indexes of the valuation give market cap
+        self.request_share_index();
+        let market_cap = self.valuation().and_then(|x| x.market_cap);
+        let (income, balance, cash_flow) = match (&self.income_statement, &self.balance_sheet, &self.cash_flow_statement) {
+            (Some(i), Some(b), Some(c)) => (&i.data, &b.data, &c.data),
+            _ => {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("正在加载财务报表...");
+                });
+                return;
+            }
+        };
+        let f_score = piotroski(income, balance, cash_flow);
+        let z_score = altman(income, balance, market_cap);
+        self.data_age_ui(ui, ViewRequestKind::Statements);
+        ui.horizontal_top(|ui| {
+            ui.vertical(|ui| {
+                match &f_score {
+                    Some(f) => {
+                        ui.strong(format!("Piotroski F-score ({}年报): {} / {}", f.year, f.score(), f.available()));
+                        Grid::new(format!("{}-f-score-grid", self.stock.symbol))
+                            .num_columns(3)
+                            .spacing([20.0, 4.0])
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for s in &f.signals {
+                                    ui.label(s.name);
+                                    ui.label(match s.passed {
+                                        Some(true) => RichText::new("✔").color(Color32::GREEN),
+                                        Some(false) => RichText::new("✘").color(Color32::RED),
+                                        None => RichText::new("--"),
+                                    });
+                                    ui.label(RichText::new(&s.detail).color(ui.visuals().weak_text_color()));
+                                    ui.end_row();
+                                }
+                            });
+                    }
+                    None => { ui.label("缺少连续两年的年报，无法计算 F-score"); }
+                }
+            });
+            ui.vertical(|ui| {
+                match &z_score {
+                    Some(z) => {
+                        match z.z() {
+                            Some(value) => {
+                                let (zone, color) = ZScore::zone(value);
+                                ui.horizontal(|ui| {
+                                    ui.strong(format!("Altman Z-score ({}): {:.2}", z.date, value));
+                                    ui.label(RichText::new(zone).color(color));
+                                });
+                            }
+                            None => { ui.strong(format!("Altman Z-score ({}): 数据不完整", z.date)); }
+                        }
+                        Grid::new(format!("{}-z-score-grid", self.stock.symbol))
+                            .num_columns(3)
+                            .spacing([20.0, 4.0])
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for (name, value, weight) in &z.components {
+                                    ui.label(*name);
+                                    ui.label(value.map(|x| format!("{:.4}", x)).unwrap_or_else(|| "--".to_string()));
+                                    ui.label(format!("× {}", weight));
+                                    ui.end_row();
+                                }
+                            });
+                        if !z.market_value {
+                            ui.label(RichText::new("未加载估值数据，X4 使用账面权益").color(ui.visuals().weak_text_color()));
+                        }
+                    }
+                    None => { ui.label("缺少年报，无法计算 Z-score"); }
+                }
+            });
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rpc::api::{BalanceSheetItem, CashFlowStatementItem, IncomeStatementItem};
+    use crate::scoring::{altman, piotroski, ZScore};
+
+    fn income(date: &str, revenue: f64, operating_cost: f64, total_profit: f64, net_profit: f64) -> IncomeStatementItem {
+        IncomeStatementItem {
+            date: date.to_string(),
+            revenue: revenue.to_string(),
+            operating_cost: operating_cost.to_string(),
+            financial_expenses: "15".to_string(),
+            total_profit: total_profit.to_string(),
+            net_profit: net_profit.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn balance(date: &str, total_assets: f64, long_term_loans: f64, current_assets: f64, paid_in_capital: f64) -> BalanceSheetItem {
+        BalanceSheetItem {
+            date: date.to_string(),
+            total_assets: total_assets.to_string(),
+            long_term_loans: long_term_loans.to_string(),
+            current_assets: current_assets.to_string(),
+            current_liabilities: "300".to_string(),
+            paid_in_capital: paid_in_capital.to_string(),
+            retained_earnings: "220".to_string(),
+            total_liabilities: "550".to_string(),
+            total_equity: (total_assets - 550.0).to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// 2022 improves on 2021 in everything but issues new shares.
+    fn fixture() -> (Vec<IncomeStatementItem>, Vec<BalanceSheetItem>, Vec<CashFlowStatementItem>) {
+        let income = vec![
+            // quarterly reports are skipped
+            income("2023-03-31", 300.0, 200.0, 40.0, 30.0),
+            income("2022-12-31", 1200.0, 780.0, 150.0, 120.0),
+            income("2021-12-31", 1000.0, 700.0, 100.0, 80.0),
+        ];
+        let balance = vec![
+            balance("2022-12-31", 1100.0, 150.0, 500.0, 120.0),
+            balance("2021-12-31", 1000.0, 200.0, 400.0, 100.0),
+        ];
+        let cash_flow = vec![CashFlowStatementItem {
+            date: "2022-12-31".to_string(),
+            net_operating_cash_flow: "150".to_string(),
+            ..Default::default()
+        }];
+        (income, balance, cash_flow)
+    }
+
+    #[test]
+    fn piotroski_signals() {
+        let (income, balance, cash_flow) = fixture();
+        let f = piotroski(&income, &balance, &cash_flow).unwrap();
+        assert_eq!(f.year, 2022);
+        let expected = [
+            ("ROA > 0", Some(true)),
+            ("经营现金流 > 0", Some(true)),
+            ("ROA 提升", Some(true)),
+            ("经营现金流 > 净利润", Some(true)),
+            ("长期杠杆下降", Some(true)),
+            ("流动比率提升", Some(true)),
+            ("未增发新股", Some(false)),
+            ("毛利率提升", Some(true)),
+            ("资产周转率提升", Some(true)),
+        ];
+        let signals: Vec<_> = f.signals.iter().map(|x| (x.name, x.passed)).collect();
+        assert_eq!(signals, expected);
+        assert_eq!((f.score(), f.available()), (8, 9));
+        // without cash flow statement
+        let f = piotroski(&income, &balance, &[]).unwrap();
+        assert_eq!(f.signals[1].passed, None);
+        assert_eq!(f.signals[3].passed, None);
+        assert_eq!((f.score(), f.available()), (6, 7));
+        // needs the year before
+        assert!(piotroski(&income[..2], &balance, &cash_flow).is_none());
+    }
+
+    #[test]
+    fn altman_z() {
+        let (income, balance, _) = fixture();
+        let z = altman(&income, &balance, None).unwrap();
+        assert_eq!(z.date, "2022-12-31");
+        assert!(!z.market_value);
+        let expected = [
+            (200.0 / 1100.0, 1.2),
+            (220.0 / 1100.0, 1.4),
+            // EBIT is total profit plus financial expenses
+            (165.0 / 1100.0, 3.3),
+            (550.0 / 550.0, 0.6),
+            (1200.0 / 1100.0, 1.0),
+        ];
+        for ((_, value, weight), (expected_value, expected_weight)) in z.components.iter().zip(expected) {
+            assert!((value.unwrap() - expected_value).abs() < 1e-9);
+            assert_eq!(*weight, expected_weight);
+        }
+        let sum: f64 = expected.iter().map(|(v, w)| v * w).sum();
+        assert!((z.z().unwrap() - sum).abs() < 1e-9);
+        assert_eq!(ZScore::zone(z.z().unwrap()).0, "灰色区");
+        for (z, zone) in [(3.0, "安全区"), (2.99, "灰色区"), (1.81, "灰色区"), (1.8, "危险区")] {
+            assert_eq!(ZScore::zone(z).0, zone);
+        }
+        // market cap replaces book equity
+        let z = altman(&income, &balance, Some(2200.0)).unwrap();
+        assert!(z.market_value);
+        assert!((z.components[3].1.unwrap() - 2200.0 / 550.0).abs() < 1e-9);
+        assert!((z.z().unwrap() - (sum + (4.0 - 1.0) * 0.6)).abs() < 1e-9);
+        assert_eq!(ZScore::zone(z.z().unwrap()).0, "安全区");
+    }
+}
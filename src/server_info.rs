@@ -0,0 +1,85 @@
+use rpc::api::{ServerInfoResp, TradingHistoryType};
+use tracing::info;
+use crate::financial_analysis::FinancialAnalysis;
+use crate::service::ServiceRequest;
+
+pub const FEATURE_PREDICT: &str = "predict";
+pub const FEATURE_STOCK_ISSUE: &str = "stock_issue";
+pub const FEATURE_GUIDE_LINE: &str = "guide_line";
+pub const FEATURE_INCOME_ANALYSIS: &str = "income_analysis";
+pub const FEATURE_STATEMENTS: &str = "statements";
+pub const FEATURE_QUOTES: &str = "quotes";
+pub const FEATURE_STOCK_SEARCH: &str = "stock_search";
+pub const FEATURE_STOCK_OVERVIEW: &str = "stock_overview";
+
+pub const ALL_TIMEFRAMES: [TradingHistoryType; 3] = [TradingHistoryType::Daily, TradingHistoryType::Week, TradingHistoryType::Month];
+
+/// What the connected server can do.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerInfo {
+    pub version: String,
+    pub timeframes: Vec<TradingHistoryType>,
+    pub predict_models: Vec<String>,
+    pub features: Vec<String>,
+    /// server without `ServerInfo`, assume it has everything
+    pub legacy: bool,
+}
+
+impl Default for ServerInfo {
+    fn default() -> Self {
+        Self {
+            version: "".to_string(),
+            timeframes: ALL_TIMEFRAMES.to_vec(),
+            predict_models: vec![],
+            features: vec![],
+            legacy: true,
+        }
+    }
+}
+
+impl From<ServerInfoResp> for ServerInfo {
+    fn from(value: ServerInfoResp) -> Self {
+        let timeframes: Vec<TradingHistoryType> = value.timeframes.into_iter()
+            .filter_map(TradingHistoryType::from_i32)
+            .collect();
+        Self {
+            version: value.version,
+            timeframes: if timeframes.is_empty() { ALL_TIMEFRAMES.to_vec() } else { timeframes },
+            predict_models: value.predict_models,
+            features: value.features,
+            legacy: false,
+        }
+    }
+}
+
+impl ServerInfo {
+    pub fn supports(&self, feature: &str) -> bool {
+        self.legacy || self.features.iter().any(|x| x == feature)
+    }
+    /// Like [`Self::supports`], but never assumed for legacy servers, for
+    /// features which change what the server is asked.
+    pub fn advertises(&self, feature: &str) -> bool {
+        self.features.iter().any(|x| x == feature)
+    }
+    pub fn supports_timeframe(&self, typ: TradingHistoryType) -> bool {
+        self.timeframes.contains(&typ)
+    }
+}
+
+impl FinancialAnalysis {
+    pub fn request_server_info(&mut self) {
+        self.call(ServiceRequest::ServerInfo);
+    }
+    pub fn set_server_info(&mut self, resp: ServerInfoResp) {
+        let server_info = ServerInfo::from(resp);
+        info!("server info: {:?}", server_info);
+        for view in &mut self.history_views {
+            view.set_server_info(server_info.clone());
+        }
+        self.server_info = server_info;
+        // the list was loaded before knowing the server searches
+        if self.stock_list_time.is_some() && self.search.server_side != self.server_side_search() {
+            self.load_stock_list();
+        }
+    }
+}
@@ -1,105 +1,724 @@
-use crate::message::{Channel, Message};
-use crate::utils::{execute, sleep_ms};
-use anyhow::Result;
+use std::future::Future;
 use std::sync::mpsc;
-use tracing::{debug, error, info};
-use crate::message::Message::LoginDone;
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::StreamExt;
+use rpc::api::{GuideLineRequest, IncomeAnalysisRequest, LoginRegisterRequest, PredictRequest, QuoteRequest, ReasonResp, StatementRequest, StockListRequest, StockIssueRequest, StockOverviewRequest, TradingHistoryItem, TradingHistoryRequest, TradingHistoryResp, TradingHistoryType};
+use tonic::Code;
+use tracing::{debug, error, info, warn};
+use crate::auth::{check_status, AuthInterceptor, SharedToken};
+use crate::cache::{blocking, guide_line_key, income_analysis_key, statement_keys, stock_issue_key, stock_list_key, trading_history_key, CacheTtl, ResponseCache};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::credential::{credential_worker, CredentialJob};
+use crate::constants::{CACHE_TTL_FINANCIAL_SECONDS, CACHE_TTL_IMMUTABLE_SECONDS, CACHE_TTL_STOCK_LIST_SECONDS};
+use crate::financial_analysis::{MainApiClient, RegisterApiClient};
+use crate::message::{Message, RequestTag, ViewRequestKind, ViewResponse};
+use crate::profile::ConnectionProfile;
+use crate::request::{cancel_pair, with_timeout, CancelGuard, CancelToken, RpcTimeouts};
+use crate::stock_view::{merge_history, TradingHistoryValueItem};
+use crate::utils::{execute, now_millis, now_seconds, sleep_ms};
 
+/// Requests handled by [`Service`], results come back to ui as [`Message`].
+#[derive(Debug)]
+pub enum ServiceRequest {
+    /// (generation, profile), drop current client and connect to the profile
+    Connect((u64, ConnectionProfile)),
+    /// connect attempt of generation succeeded, sent by the service itself
+    Connected((u64, MainApiClient, RegisterApiClient)),
+    SetTimeouts(RpcTimeouts),
+    ClearCache,
+    Login(LoginRegisterRequest),
+    Register(LoginRegisterRequest),
+    RefreshToken,
+    Ping,
+    ServerInfo,
+    /// (generation, request)
+    StockList((u64, StockListRequest)),
+    /// symbols to receive `Message::GotQuote` of, replacing the previous subscription
+    SubscribeQuotes(Vec<String>),
+    /// request of a stock view, answered with `Message::ViewResponse` of the same tag
+    View((RequestTag, ViewRequest)),
+    #[cfg(not(target_arch = "wasm32"))]
+    Credential(CredentialJob),
+}
+
+#[derive(Debug, Clone)]
+pub enum ViewRequest {
+    /// (symbol, timeframe, since), see `TradingHistoryRequest.since`
+    TradingHistory((String, TradingHistoryType, String)),
+    /// (symbol, history, length, model)
+    Predict((String, Vec<TradingHistoryValueItem>, u32, String)),
+    /// code
+    StockIssue(String),
+    /// (code, year)
+    GuideLine((String, String)),
+    /// (code, years)
+    IncomeAnalysis((String, u32)),
+    /// code, requests all three statements
+    Statements(String),
+    /// trading history with issue and income analysis when asked, answered part by part
+    Overview(StockOverviewRequest),
+}
+
+impl ViewRequest {
+    /// Kinds of responses answering this request.
+    pub fn kinds(&self) -> Vec<ViewRequestKind> {
+        match self {
+            ViewRequest::TradingHistory(_) => vec![ViewRequestKind::TradingHistory],
+            ViewRequest::Predict(_) => vec![ViewRequestKind::Predict],
+            ViewRequest::StockIssue(_) => vec![ViewRequestKind::StockIssue],
+            ViewRequest::GuideLine(_) => vec![ViewRequestKind::GuideLine],
+            ViewRequest::IncomeAnalysis(_) => vec![ViewRequestKind::IncomeAnalysis],
+            ViewRequest::Statements(_) => vec![ViewRequestKind::Statements],
+            ViewRequest::Overview(request) => {
+                let mut kinds = vec![ViewRequestKind::TradingHistory];
+                if request.issue {
+                    kinds.push(ViewRequestKind::StockIssue);
+                }
+                if request.income_analysis {
+                    kinds.push(ViewRequestKind::IncomeAnalysis);
+                }
+                kinds
+            }
+        }
+    }
+    /// Responses telling the view this request failed, so it stops waiting.
+    pub fn failed(&self, error: String) -> Vec<ViewResponse> {
+        match self {
+            ViewRequest::TradingHistory((_, _, since)) => vec![ViewResponse::TradingHistory((since.to_string(), vec![], error))],
+            ViewRequest::Predict(_) => vec![ViewResponse::Predicts((vec![], error))],
+            ViewRequest::StockIssue(_) => vec![ViewResponse::StockIssue((Default::default(), error))],
+            ViewRequest::GuideLine(_) => vec![ViewResponse::GuideLine((Default::default(), error))],
+            ViewRequest::IncomeAnalysis(_) => vec![ViewResponse::IncomeAnalysis((Default::default(), error))],
+            ViewRequest::Statements(_) => vec![ViewResponse::CashFlowStatement((Default::default(), error))],
+            ViewRequest::Overview(request) => {
+                let mut responses = vec![ViewResponse::TradingHistory((request.since.to_string(), vec![], error.to_string()))];
+                if request.issue {
+                    responses.push(ViewResponse::StockIssue((Default::default(), error.to_string())));
+                }
+                if request.income_analysis {
+                    responses.push(ViewResponse::IncomeAnalysis((Default::default(), error.to_string())));
+                }
+                responses
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ServiceCall {
+    pub request: ServiceRequest,
+    /// drop the request when cancelled
+    pub cancel: Option<CancelToken>,
+    /// answer from cache when there is a fresh response
+    pub use_cache: bool,
+}
+
+/// Cheap handle to send requests to [`Service`].
+#[derive(Debug, Clone)]
+pub struct ServiceHandle {
+    tx: UnboundedSender<ServiceCall>,
+}
+
+impl ServiceHandle {
+    pub fn send(&self, request: ServiceRequest) {
+        self.call(ServiceCall { request, cancel: None, use_cache: true });
+    }
+    pub fn send_cancellable(&self, request: ServiceRequest, cancel: &CancelToken, use_cache: bool) {
+        self.call(ServiceCall { request, cancel: Some(cancel.clone()), use_cache });
+    }
+    fn call(&self, call: ServiceCall) {
+        if let Err(e) = self.tx.unbounded_send(call) {
+            error!("service stopped: {}", e);
+        }
+    }
+}
+
+/// Actor owning the api clients, performs every RPC for the ui.
 pub struct Service {
-    pub channel: Channel,
-    pub self_loop: Channel,
+    rx: UnboundedReceiver<ServiceCall>,
+    /// feeds results of connect attempts back to the actor
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    handle: ServiceHandle,
+    tx: mpsc::Sender<Message>,
+    token: SharedToken,
+    client: Option<MainApiClient>,
+    register_client: Option<RegisterApiClient>,
+    generation: u64,
+    timeouts: RpcTimeouts,
+    cache: ResponseCache,
+    quote_symbols: Vec<String>,
+    /// dropping it stops the running quote subscription
+    quote_guard: Option<CancelGuard>,
+    /// queue of [`credential_worker`]
+    #[cfg(not(target_arch = "wasm32"))]
+    credential_jobs: UnboundedSender<CredentialJob>,
 }
 
-unsafe impl Send for Service {}
+#[cfg(not(target_arch = "wasm32"))]
+fn spawn<F: Future<Output=()> + Send + 'static>(cancel: Option<CancelToken>, f: F) {
+    execute(async move {
+        match cancel {
+            Some(cancel) => { cancel.run(f).await; }
+            None => f.await,
+        }
+    });
+}
+
+#[cfg(target_arch = "wasm32")]
+fn spawn<F: Future<Output=()> + 'static>(cancel: Option<CancelToken>, f: F) {
+    execute(async move {
+        match cancel {
+            Some(cancel) => { cancel.run(f).await; }
+            None => f.await,
+        }
+    });
+}
+
+/// Reply for requests arriving without a client, so the ui stops waiting.
+fn not_connected(request: &ServiceRequest, tx: &mpsc::Sender<Message>) {
+    let error = "未连接到服务器".to_string();
+    let msg = match request {
+        ServiceRequest::Login(_) => Message::LoginError(error),
+        ServiceRequest::Register(_) => Message::RegisterDone(ReasonResp { err: true, reason: error }),
+        ServiceRequest::RefreshToken => Message::TokenRefreshError(error),
+        ServiceRequest::Ping => Message::Pong((None, error)),
+        ServiceRequest::StockList((generation, _)) => Message::StockListError((*generation, error)),
+        ServiceRequest::View((tag, request)) => {
+            for response in request.failed(error) {
+                let _ = tx.send(Message::ViewResponse((*tag, response, 0)));
+            }
+            return;
+        }
+        _ => return,
+    };
+    let _ = tx.send(msg);
+}
 
 impl Service {
-    async fn handle_message(&mut self, msg: Message) -> Result<bool> {
-        info!("service handle msg: {:?}", msg);
-        match msg {
-            Message::ApiClientConnect(_) => {}
-            Message::LoginDone(m) => { self.channel.tx.send(LoginDone(m)).unwrap(); }
-            _ => {}
+    /// Start the service, responses are sent to `tx`.
+    pub fn start(tx: mpsc::Sender<Message>, token: SharedToken) -> ServiceHandle {
+        debug!("starting service...");
+        let (call_tx, rx) = unbounded();
+        let handle = ServiceHandle { tx: call_tx };
+        #[cfg(not(target_arch = "wasm32"))]
+        let credential_jobs = {
+            let (jobs, rx) = unbounded();
+            execute(credential_worker(rx, tx.clone()));
+            jobs
+        };
+        let service = Self {
+            rx,
+            handle: handle.clone(),
+            tx,
+            token,
+            client: None,
+            register_client: None,
+            generation: 0,
+            timeouts: Default::default(),
+            cache: Default::default(),
+            quote_symbols: vec![],
+            quote_guard: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            credential_jobs,
+        };
+        execute(service.run());
+        debug!("service started");
+        handle
+    }
+
+    async fn run(mut self) {
+        info!("service starts");
+        while let Some(call) = self.rx.next().await {
+            self.handle_call(call);
         }
-        Ok(false)
+        info!("service stopped");
     }
 
-    pub fn new(channel: Channel) -> Self {
-        let (channel_loop_tx, channel_loop_rx) = mpsc::channel();
-        let self_loop = Channel {
-            tx: channel_loop_tx,
-            rx: channel_loop_rx,
-        };
-        Self {
-            channel,
-            self_loop,
-        }
-    }
-
-    pub async fn run(&mut self) {
-        loop {
-            sleep_ms(10).await;
-            {
-                let r = self
-                    .channel
-                    .rx
-                    .try_recv()
-                    .map(|msg| self.handle_message(msg));
-                if let Ok(r) = r {
-                    match r.await {
-                        Ok(will_break) => {
-                            if will_break {
-                                break;
+    fn handle_call(&mut self, call: ServiceCall) {
+        let ServiceCall { request, cancel, use_cache } = call;
+        debug!("service handle request: {:?}", request);
+        match request {
+            ServiceRequest::Connect((generation, profile)) => self.connect(generation, profile),
+            ServiceRequest::Connected((generation, client, register_client)) => {
+                if generation == self.generation {
+                    info!("got api client: {:?}", client);
+                    self.client = Some(client);
+                    self.register_client = Some(register_client);
+                    let _ = self.tx.send(Message::ApiClientConnect(generation));
+                    self.subscribe_quotes();
+                }
+            }
+            ServiceRequest::SetTimeouts(timeouts) => self.timeouts = timeouts,
+            ServiceRequest::ClearCache => {
+                let cache = self.cache.clone();
+                spawn(None, blocking(move || cache.clear()));
+            }
+            ServiceRequest::SubscribeQuotes(symbols) => {
+                self.quote_symbols = symbols;
+                self.subscribe_quotes();
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            ServiceRequest::Credential(job) => {
+                if let Err(e) = self.credential_jobs.unbounded_send(job) {
+                    error!("credential worker stopped: {}", e);
+                }
+            }
+            request => {
+                let clients = self.client.clone().zip(self.register_client.clone());
+                let (tx, timeouts, cache) = (self.tx.clone(), self.timeouts, self.cache.clone());
+                // cache is looked up in the request task, the actor doesn't wait on files
+                spawn(cancel, async move {
+                    // without a client, expired responses are still better than nothing
+                    let stale = clients.is_none();
+                    let cacheable = matches!(request, ServiceRequest::StockList(_) | ServiceRequest::View(_));
+                    let request = if cacheable && (use_cache || stale) {
+                        let lookup = cache.clone();
+                        let (request, cached) = blocking(move || {
+                            let cached = lookup.lookup(&request, stale);
+                            (request, cached)
+                        }).await;
+                        if let Some(messages) = cached {
+                            for msg in messages {
+                                let _ = tx.send(msg);
                             }
+                            return;
                         }
-                        Err(e) => {
-                            error!("service run error: {}", e);
-                            break;
-                        }
+                        request
+                    } else {
+                        request
                     };
+                    match clients {
+                        Some((client, register_client)) => perform(client, register_client, tx, timeouts, cache, request).await,
+                        None => {
+                            warn!("no client for {:?}", request);
+                            not_connected(&request, &tx);
+                        }
+                    }
+                });
+            }
+        }
+    }
+
+    fn connect(&mut self, generation: u64, profile: ConnectionProfile) {
+        self.generation = generation;
+        self.cache.set_scope(&profile.url());
+        self.client = None;
+        self.register_client = None;
+        self.quote_guard = None;
+        let interceptor = AuthInterceptor::new(self.token.clone());
+        let tx = self.tx.clone();
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let endpoint = match profile.endpoint() {
+                Ok(endpoint) => endpoint,
+                Err(e) => {
+                    let _ = tx.send(Message::ConnectFailed((generation, format!("{:#}", e))));
+                    return;
+                }
+            };
+            let handle = self.handle.clone();
+            spawn(None, async move {
+                match endpoint.connect().await {
+                    Ok(channel) => {
+                        let register_client: RegisterApiClient = rpc::api::register_client::RegisterClient::new(channel.clone());
+                        let client: MainApiClient = rpc::api::api_rpc_client::ApiRpcClient::with_interceptor(channel, interceptor);
+                        handle.send(ServiceRequest::Connected((generation, client, register_client)));
+                    }
+                    Err(e) => {
+                        let _ = tx.send(Message::ConnectFailed((generation, crate::connection::error_chain(&e))));
+                    }
+                }
+            });
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            // grpc-web has no connection to set up, failures show up on the first calls
+            let inner = tonic_web_wasm_client::Client::new(profile.url());
+            let register_client: RegisterApiClient = rpc::api::register_client::RegisterClient::new(inner.clone());
+            let client: MainApiClient = rpc::api::api_rpc_client::ApiRpcClient::with_interceptor(inner, interceptor);
+            self.client = Some(client);
+            self.register_client = Some(register_client);
+            let _ = tx.send(Message::ApiClientConnect(generation));
+            self.subscribe_quotes();
+        }
+    }
+
+    /// (Re)start the quote subscription of `quote_symbols` with current client.
+    fn subscribe_quotes(&mut self) {
+        self.quote_guard = None;
+        let Some(client) = self.client.clone() else { return; };
+        if self.quote_symbols.is_empty() {
+            return;
+        }
+        info!("subscribing quotes of {:?}", self.quote_symbols);
+        let (guard, cancel) = cancel_pair();
+        self.quote_guard = Some(guard);
+        spawn(Some(cancel), quotes(client, self.tx.clone(), self.timeouts, self.quote_symbols.clone()));
+    }
+}
+
+async fn perform(mut client: MainApiClient, mut register_client: RegisterApiClient,
+                 tx: mpsc::Sender<Message>, timeouts: RpcTimeouts, cache: ResponseCache, request: ServiceRequest) {
+    match request {
+        ServiceRequest::Login(request) => {
+            let msg = match client.login(with_timeout(request, timeouts.auth)).await {
+                Ok(r) => {
+                    let data = r.into_inner();
+                    if data.err {
+                        error!("{}", data.reason);
+                        Message::LoginError(data.reason)
+                    } else {
+                        Message::LoginDone(data.token)
+                    }
+                }
+                Err(e) => {
+                    error!("{}", e);
+                    check_status(&e, &tx);
+                    Message::LoginError(e.message().to_string())
+                }
+            };
+            let _ = tx.send(msg);
+        }
+        ServiceRequest::Register(request) => {
+            let res = match register_client.register(with_timeout(request, timeouts.auth)).await {
+                Ok(r) => r.into_inner(),
+                Err(e) => {
+                    error!("{}", e);
+                    check_status(&e, &tx);
+                    ReasonResp { err: true, reason: e.to_string() }
+                }
+            };
+            info!("register resp: {:?}", res);
+            let _ = tx.send(Message::RegisterDone(res));
+        }
+        ServiceRequest::RefreshToken => {
+            info!("refreshing token");
+            let msg = match client.refresh_token(with_timeout((), timeouts.auth)).await {
+                Ok(r) => {
+                    let data = r.into_inner();
+                    if data.err {
+                        Message::TokenRefreshError(data.reason)
+                    } else {
+                        Message::TokenRefreshed(data.token)
+                    }
+                }
+                Err(e) => {
+                    check_status(&e, &tx);
+                    Message::TokenRefreshError(e.to_string())
+                }
+            };
+            let _ = tx.send(msg);
+        }
+        ServiceRequest::Ping => {
+            let start = now_millis();
+            let msg = match client.ping(with_timeout((), timeouts.ping)).await {
+                Ok(_) => Message::Pong((Some((now_millis() - start) as f32), "".to_string())),
+                Err(e) => {
+                    warn!("ping failed: {}", e);
+                    check_status(&e, &tx);
+                    Message::Pong((None, e.to_string()))
+                }
+            };
+            let _ = tx.send(msg);
+        }
+        ServiceRequest::ServerInfo => {
+            match client.server_info(with_timeout((), timeouts.auth)).await {
+                Ok(r) => {
+                    let _ = tx.send(Message::GotServerInfo(r.into_inner()));
+                }
+                Err(e) if e.code() == Code::Unimplemented => {
+                    info!("server has no ServerInfo, assuming full features");
+                }
+                Err(e) => {
+                    warn!("get server info failed: {}", e);
+                    check_status(&e, &tx);
+                }
+            }
+        }
+        ServiceRequest::StockList((generation, request)) => {
+            info!("requesting stock list: {:?}", request);
+            match client.stock_list(with_timeout(request.clone(), timeouts.stock_list)).await {
+                Ok(r) => {
+                    let stock = r.into_inner();
+                    info!("got stock_list: {}/{}", stock.data.len(), stock.total);
+                    cache.put(&stock_list_key(&request), &stock, CacheTtl::Seconds(CACHE_TTL_STOCK_LIST_SECONDS));
+                    let _ = tx.send(Message::GotStockList((generation, request, stock, now_seconds())));
+                }
+                Err(e) => {
+                    error!("{}", e);
+                    check_status(&e, &tx);
+                    let _ = tx.send(Message::StockListError((generation, e.to_string())));
+                }
+            }
+        }
+        ServiceRequest::View((tag, request)) => perform_view(client, tx, timeouts, cache, tag, request).await,
+        request => warn!("unexpected request in perform: {:?}", request),
+    }
+}
+
+async fn perform_view(mut client: MainApiClient, tx: mpsc::Sender<Message>, timeouts: RpcTimeouts,
+                      cache: ResponseCache, tag: RequestTag, request: ViewRequest) {
+    let reply_tx = tx.clone();
+    let reply = move |response: ViewResponse| {
+        let _ = reply_tx.send(Message::ViewResponse((tag, response, now_seconds())));
+    };
+    match request {
+        ViewRequest::TradingHistory((symbol, typ, since)) => {
+            let key = trading_history_key(&symbol, typ);
+            let (cached, cursor) = history_cursor(&cache, &key, &since).await;
+            let request = TradingHistoryRequest { symbol: symbol.clone(), typ: typ as i32, since: cursor.clone() };
+            let msg = match client.trading_history(with_timeout(request, timeouts.trading_history)).await {
+                Ok(r) => {
+                    let bars = r.into_inner().data;
+                    info!("get trading history done: {}, {} bars since '{}'", symbol, bars.len(), cursor);
+                    history_response(&cache, &key, cached, cursor, since, bars)
+                }
+                Err(e) => {
+                    error!("{}", e);
+                    check_status(&e, &tx);
+                    ViewResponse::TradingHistory((since, vec![], e.to_string()))
+                }
+            };
+            reply(msg);
+        }
+        ViewRequest::Predict((_, raw_data, length, model)) => {
+            let data_list: Vec<Vec<f32>> = vec![
+                raw_data.iter().map(|x| x.high).collect(),
+                raw_data.iter().map(|x| x.low).collect(),
+                raw_data.iter().map(|x| x.open).collect(),
+                raw_data.iter().map(|x| x.close).collect(),
+            ];
+            // predict the four series at the same time
+            let requests = data_list.into_iter().enumerate().map(|(i, data)| {
+                let mut client = client.clone();
+                let request = PredictRequest { data, length, model: model.clone() };
+                info!("requesting new predict... {}/4", i);
+                async move { client.predict_data(with_timeout(request, timeouts.predict)).await }
+            });
+            let mut results: Vec<Vec<f32>> = vec![];
+            let mut errors = vec![];
+            for r in futures::future::join_all(requests).await {
+                match r {
+                    Ok(r) => results.push(r.into_inner().data),
+                    Err(e) => {
+                        check_status(&e, &tx);
+                        errors.push(e.to_string());
+                    }
                 }
             }
-            {
-                let r = self
-                    .self_loop
-                    .rx
-                    .try_recv()
-                    .map(|msg| self.handle_message(msg));
-                if let Ok(r) = r {
-                    match r.await {
-                        Ok(will_break) => {
-                            if will_break {
-                                break;
+            let len = results.iter().map(|x| x.len()).min().unwrap_or(0);
+            let msg = if results.len() != 4 || len == 0 {
+                let e = format!("Errors: {:?}", errors);
+                error!("{}", e);
+                ViewResponse::Predicts((vec![], e))
+            } else {
+                info!("got {} predicts", len);
+                let predicts = (0..len).map(|i| {
+                    let mut p = TradingHistoryValueItem::new("");
+                    p.high = results[0][i];
+                    p.low = results[1][i];
+                    p.open = results[2][i];
+                    p.close = results[3][i];
+                    p.volume = 1;
+                    p
+                }).collect();
+                ViewResponse::Predicts((predicts, "".to_string()))
+            };
+            reply(msg);
+        }
+        ViewRequest::StockIssue(code) => {
+            let key = stock_issue_key(&code);
+            let rpc = client.stock_issue(with_timeout(StockIssueRequest { symbol: code }, timeouts.financial));
+            reply(ViewResponse::StockIssue(fetch(rpc, &tx, &cache, &key, CacheTtl::Seconds(CACHE_TTL_IMMUTABLE_SECONDS)).await));
+        }
+        ViewRequest::GuideLine((code, year)) => {
+            let key = guide_line_key(&code, &year);
+            let rpc = client.guide_line(with_timeout(GuideLineRequest { code, year }, timeouts.financial));
+            reply(ViewResponse::GuideLine(fetch(rpc, &tx, &cache, &key, CacheTtl::Seconds(CACHE_TTL_FINANCIAL_SECONDS)).await));
+        }
+        ViewRequest::IncomeAnalysis((code, years)) => {
+            let key = income_analysis_key(&code, years);
+            let rpc = client.income_analysis(with_timeout(IncomeAnalysisRequest { code, years }, timeouts.financial));
+            reply(ViewResponse::IncomeAnalysis(fetch(rpc, &tx, &cache, &key, CacheTtl::Seconds(CACHE_TTL_FINANCIAL_SECONDS)).await));
+        }
+        ViewRequest::Statements(code) => {
+            info!("requesting statements of {}", code);
+            let [income_key, balance_key, cash_flow_key] = statement_keys(&code);
+            let ttl = CacheTtl::Seconds(CACHE_TTL_FINANCIAL_SECONDS);
+            let request = StatementRequest { code, periods: 0 };
+            let rpc = client.income_statement(with_timeout(request.clone(), timeouts.financial));
+            reply(ViewResponse::IncomeStatement(fetch(rpc, &tx, &cache, &income_key, ttl).await));
+            let rpc = client.balance_sheet(with_timeout(request.clone(), timeouts.financial));
+            reply(ViewResponse::BalanceSheet(fetch(rpc, &tx, &cache, &balance_key, ttl).await));
+            // the last one of statements, ui stops waiting on it
+            let rpc = client.cash_flow_statement(with_timeout(request, timeouts.financial));
+            reply(ViewResponse::CashFlowStatement(fetch(rpc, &tx, &cache, &cash_flow_key, ttl).await));
+        }
+        ViewRequest::Overview(mut request) => {
+            info!("requesting overview of {}", request.symbol);
+            let key = trading_history_key(&request.symbol, request.typ());
+            let since = request.since.to_string();
+            let (cached, cursor) = history_cursor(&cache, &key, &since).await;
+            request.since = cursor.to_string();
+            let (issue, income_analysis) = (request.issue, request.income_analysis);
+            let issue_key = stock_issue_key(&request.code);
+            let income_key = income_analysis_key(&request.code, request.income_years);
+            let timeout = timeouts.trading_history.max(timeouts.financial);
+            match client.stock_overview(with_timeout(request, timeout)).await {
+                Ok(r) => {
+                    let r = r.into_inner();
+                    // a missing part without error is just as unusable
+                    let error = |e: String| if e.is_empty() { "服务器未返回数据".to_string() } else { e };
+                    reply(match r.trading_history {
+                        Some(history) => history_response(&cache, &key, cached, cursor, since, history.data),
+                        None => ViewResponse::TradingHistory((since, vec![], error(r.trading_history_error))),
+                    });
+                    if issue {
+                        reply(match r.issue {
+                            Some(r) => {
+                                cache.put(&issue_key, &r, CacheTtl::Seconds(CACHE_TTL_IMMUTABLE_SECONDS));
+                                ViewResponse::StockIssue((r, "".to_string()))
+                            }
+                            None => ViewResponse::StockIssue((Default::default(), error(r.issue_error))),
+                        });
+                    }
+                    if income_analysis {
+                        reply(match r.income_analysis {
+                            Some(r) => {
+                                cache.put(&income_key, &r, CacheTtl::Seconds(CACHE_TTL_FINANCIAL_SECONDS));
+                                ViewResponse::IncomeAnalysis((r, "".to_string()))
                             }
+                            None => ViewResponse::IncomeAnalysis((Default::default(), error(r.income_analysis_error))),
+                        });
+                    }
+                }
+                Err(e) => {
+                    error!("{}", e);
+                    check_status(&e, &tx);
+                    let failed = ViewRequest::Overview(StockOverviewRequest { since, issue, income_analysis, ..Default::default() });
+                    for msg in failed.failed(e.to_string()) {
+                        reply(msg);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Await an RPC answered by a single response, which is cached under `key` for `ttl`.
+/// On failure the response is empty and the error text is returned with it.
+async fn fetch<T: prost::Message + Default>(rpc: impl Future<Output=Result<tonic::Response<T>, tonic::Status>>,
+                                            tx: &mpsc::Sender<Message>, cache: &ResponseCache, key: &str, ttl: CacheTtl) -> (T, String) {
+    match rpc.await {
+        Ok(r) => {
+            let r = r.into_inner();
+            cache.put(key, &r, ttl);
+            (r, "".to_string())
+        }
+        Err(e) => {
+            error!("{}", e);
+            check_status(&e, tx);
+            (Default::default(), e.to_string())
+        }
+    }
+}
+
+/// Cached history of `key` and the cursor to fetch what's after it, see `ViewRequest::TradingHistory`.
+async fn history_cursor(cache: &ResponseCache, key: &str, since: &str) -> (Option<TradingHistoryResp>, String) {
+    let (cache, key) = (cache.clone(), key.to_string());
+    // cached history, even expired, only misses the latest bars
+    let cached: Option<TradingHistoryResp> = blocking(move || cache.get(&key, true).map(|(r, _)| r)).await;
+    let cached_last = cached.as_ref().and_then(|x| x.data.last()).map(|x| x.date.to_string());
+    let cursor = match cached_last {
+        Some(last) if since.is_empty() || last.as_str() < since => last,
+        _ => since.to_string(),
+    };
+    (cached, cursor)
+}
+
+/// Merge `bars` fetched since `cursor` into the cache, replying what the view asked with `since`.
+fn history_response(cache: &ResponseCache, key: &str, cached: Option<TradingHistoryResp>, cursor: String,
+                    since: String, bars: Vec<TradingHistoryItem>) -> ViewResponse {
+    let full = match cached {
+        Some(cached) if !cursor.is_empty() => {
+            let mut data = cached.data;
+            merge_history(&mut data, bars.clone(), |x| x.date.as_str());
+            Some(data)
+        }
+        // can't tell what's before the cursor without cache
+        _ if !cursor.is_empty() => None,
+        _ => Some(bars.clone()),
+    };
+    match full {
+        Some(full) => {
+            let full = TradingHistoryResp { data: full };
+            cache.put(key, &full, CacheTtl::UntilMarketClose);
+            if since.is_empty() {
+                ViewResponse::TradingHistory(("".to_string(), full.data, "".to_string()))
+            } else {
+                ViewResponse::TradingHistory((since, bars, "".to_string()))
+            }
+        }
+        None => ViewResponse::TradingHistory((cursor, bars, "".to_string())),
+    }
+}
+
+/// Stream quotes until cancelled, resubscribing when the stream breaks.
+#[cfg(not(target_arch = "wasm32"))]
+async fn quotes(mut client: MainApiClient, tx: mpsc::Sender<Message>, _timeouts: RpcTimeouts, symbols: Vec<String>) {
+    loop {
+        match client.subscribe_quotes(QuoteRequest { symbols: symbols.clone() }).await {
+            Ok(r) => {
+                let mut stream = r.into_inner();
+                loop {
+                    match stream.message().await {
+                        Ok(Some(quote)) => {
+                            let _ = tx.send(Message::GotQuote(quote));
+                        }
+                        Ok(None) => {
+                            info!("quote stream ended");
+                            break;
                         }
                         Err(e) => {
-                            error!("service loop run error: {}", e);
+                            warn!("quote stream broken: {}", e);
+                            check_status(&e, &tx);
                             break;
                         }
-                    };
+                    }
                 }
             }
+            Err(e) if e.code() == Code::Unimplemented => {
+                info!("server has no quotes");
+                return;
+            }
+            Err(e) => {
+                warn!("subscribe quotes failed: {}", e);
+                check_status(&e, &tx);
+            }
         }
-        #[cfg(not(target_arch = "wasm32"))]
-        info!("[thread-{:?}] service stopped", std::thread::current().id());
-        #[cfg(target_arch = "wasm32")]
-        info!("service stopped");
-    }
-
-    pub fn start(channel: Channel) {
-        debug!("starting service...");
-        // execute(async move {
-        execute(async move {
-            run_service(channel).await;
-        });
-        debug!("service started");
+        sleep_ms(crate::constants::QUOTE_RETRY_SECONDS * 1000).await;
     }
 }
 
-async fn run_service(channel: Channel) {
-    let mut s = Service::new(channel);
-    #[cfg(not(target_arch = "wasm32"))]
-    info!("[thread-{:?}] service starts", std::thread::current().id());
-    #[cfg(target_arch = "wasm32")]
-    info!("service starts");
-    s.run().await
+/// grpc-web can't keep a response stream open, poll the latest quotes instead.
+#[cfg(target_arch = "wasm32")]
+async fn quotes(mut client: MainApiClient, tx: mpsc::Sender<Message>, timeouts: RpcTimeouts, symbols: Vec<String>) {
+    loop {
+        match client.quotes(with_timeout(QuoteRequest { symbols: symbols.clone() }, timeouts.ping)).await {
+            Ok(r) => {
+                for quote in r.into_inner().data {
+                    let _ = tx.send(Message::GotQuote(quote));
+                }
+            }
+            Err(e) if e.code() == Code::Unimplemented => {
+                info!("server has no quotes");
+                return;
+            }
+            Err(e) => {
+                warn!("poll quotes failed: {}", e);
+                check_status(&e, &tx);
+            }
+        }
+        sleep_ms(crate::constants::QUOTE_POLL_SECONDS * 1000).await;
+    }
 }
@@ -0,0 +1,377 @@
+use egui::{Color32, ComboBox, Grid, RichText, ScrollArea, Ui};
+use rpc::api::{BalanceSheetItem, CashFlowStatementItem, IncomeStatementItem};
+use crate::message::ViewRequestKind;
+use crate::stock_view::{StockView, StockViewTab};
+use crate::service::ViewRequest;
+use crate::utils::{format_amount, parse_value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatementChange {
+    None,
+    /// compare with the same period of last year
+    YoY,
+    /// compare with the previous reporting period
+    QoQ,
+}
+
+pub struct StatementRow<T> {
+    pub label: &'static str,
+    pub value: fn(&T) -> &str,
+}
+
+/// A raw financial statement of one reporting period.
+pub trait Statement: Sized + 'static {
+    /// rows shown in the statement grid, in display order
+    const ROWS: &'static [StatementRow<Self>];
+    /// name of the common-size denominator
+    const BASE_LABEL: &'static str;
+    /// values add up from the start of the year, not a balance at the date
+    const CUMULATIVE: bool;
+    fn date(&self) -> &str;
+    /// common-size denominator, revenue or total assets
+    fn base(&self) -> &str;
+}
+
+impl Statement for IncomeStatementItem {
+    const ROWS: &'static [StatementRow<Self>] = &[
+        StatementRow { label: "营业总收入", value: |x| x.revenue.as_str() },
+        StatementRow { label: "营业成本", value: |x| x.operating_cost.as_str() },
+        StatementRow { label: "税金及附加", value: |x| x.taxes_and_surcharges.as_str() },
+        StatementRow { label: "销售费用", value: |x| x.selling_expenses.as_str() },
+        StatementRow { label: "管理费用", value: |x| x.admin_expenses.as_str() },
+        StatementRow { label: "研发费用", value: |x| x.rd_expenses.as_str() },
+        StatementRow { label: "财务费用", value: |x| x.financial_expenses.as_str() },
+        StatementRow { label: "营业利润", value: |x| x.operating_profit.as_str() },
+        StatementRow { label: "营业外收入", value: |x| x.non_operating_income.as_str() },
+        StatementRow { label: "营业外支出", value: |x| x.non_operating_expenses.as_str() },
+        StatementRow { label: "利润总额", value: |x| x.total_profit.as_str() },
+        StatementRow { label: "所得税费用", value: |x| x.income_tax.as_str() },
+        StatementRow { label: "净利润", value: |x| x.net_profit.as_str() },
+        StatementRow { label: "归属于母公司的净利润", value: |x| x.net_profit_parent.as_str() },
+    ];
+    const BASE_LABEL: &'static str = "营业总收入";
+    const CUMULATIVE: bool = true;
+    fn date(&self) -> &str {
+        self.date.as_str()
+    }
+    fn base(&self) -> &str {
+        self.revenue.as_str()
+    }
+}
+
+impl Statement for BalanceSheetItem {
+    const ROWS: &'static [StatementRow<Self>] = &[
+        StatementRow { label: "货币资金", value: |x| x.cash.as_str() },
+        StatementRow { label: "应收票据", value: |x| x.notes_receivable.as_str() },
+        StatementRow { label: "应收账款", value: |x| x.accounts_receivable.as_str() },
+        StatementRow { label: "预付款项", value: |x| x.prepayments.as_str() },
+        StatementRow { label: "存货", value: |x| x.inventories.as_str() },
+        StatementRow { label: "流动资产合计", value: |x| x.current_assets.as_str() },
+        StatementRow { label: "长期股权投资", value: |x| x.long_term_investments.as_str() },
+        StatementRow { label: "固定资产", value: |x| x.fixed_assets.as_str() },
+        StatementRow { label: "在建工程", value: |x| x.construction_in_progress.as_str() },
+        StatementRow { label: "无形资产", value: |x| x.intangible_assets.as_str() },
+        StatementRow { label: "商誉", value: |x| x.goodwill.as_str() },
+        StatementRow { label: "非流动资产合计", value: |x| x.non_current_assets.as_str() },
+        StatementRow { label: "资产总计", value: |x| x.total_assets.as_str() },
+        StatementRow { label: "短期借款", value: |x| x.short_term_loans.as_str() },
+        StatementRow { label: "应付票据", value: |x| x.notes_payable.as_str() },
+        StatementRow { label: "应付账款", value: |x| x.accounts_payable.as_str() },
+        StatementRow { label: "预收款项", value: |x| x.advance_receipts.as_str() },
+        StatementRow { label: "流动负债合计", value: |x| x.current_liabilities.as_str() },
+        StatementRow { label: "长期借款", value: |x| x.long_term_loans.as_str() },
+        StatementRow { label: "应付债券", value: |x| x.bonds_payable.as_str() },
+        StatementRow { label: "非流动负债合计", value: |x| x.non_current_liabilities.as_str() },
+        StatementRow { label: "负债合计", value: |x| x.total_liabilities.as_str() },
+        StatementRow { label: "实收资本(或股本)", value: |x| x.paid_in_capital.as_str() },
+        StatementRow { label: "资本公积", value: |x| x.capital_reserve.as_str() },
+        StatementRow { label: "未分配利润", value: |x| x.retained_earnings.as_str() },
+        StatementRow { label: "所有者权益合计", value: |x| x.total_equity.as_str() },
+    ];
+    const BASE_LABEL: &'static str = "资产总计";
+    const CUMULATIVE: bool = false;
+    fn date(&self) -> &str {
+        self.date.as_str()
+    }
+    fn base(&self) -> &str {
+        self.total_assets.as_str()
+    }
+}
+
+impl Statement for CashFlowStatementItem {
+    const ROWS: &'static [StatementRow<Self>] = &[
+        StatementRow { label: "销售商品、提供劳务收到的现金", value: |x| x.cash_from_sales.as_str() },
+        StatementRow { label: "经营活动现金流入小计", value: |x| x.operating_inflow.as_str() },
+        StatementRow { label: "经营活动现金流出小计", value: |x| x.operating_outflow.as_str() },
+        StatementRow { label: "经营活动产生的现金流量净额", value: |x| x.net_operating_cash_flow.as_str() },
+        StatementRow { label: "投资活动现金流入小计", value: |x| x.investing_inflow.as_str() },
+        StatementRow { label: "购建固定资产等支付的现金", value: |x| x.capital_expenditure.as_str() },
+        StatementRow { label: "投资活动现金流出小计", value: |x| x.investing_outflow.as_str() },
+        StatementRow { label: "投资活动产生的现金流量净额", value: |x| x.net_investing_cash_flow.as_str() },
+        StatementRow { label: "筹资活动现金流入小计", value: |x| x.financing_inflow.as_str() },
+        StatementRow { label: "分配股利、利润或偿付利息支付的现金", value: |x| x.dividends_paid.as_str() },
+        StatementRow { label: "筹资活动现金流出小计", value: |x| x.financing_outflow.as_str() },
+        StatementRow { label: "筹资活动产生的现金流量净额", value: |x| x.net_financing_cash_flow.as_str() },
+        StatementRow { label: "现金及现金等价物净增加额", value: |x| x.net_cash_flow.as_str() },
+        StatementRow { label: "期末现金及现金等价物余额", value: |x| x.ending_cash.as_str() },
+    ];
+    // cash flow statement has no revenue line, use cash received from sales instead
+    const BASE_LABEL: &'static str = "销售商品、提供劳务收到的现金";
+    const CUMULATIVE: bool = true;
+    fn date(&self) -> &str {
+        self.date.as_str()
+    }
+    fn base(&self) -> &str {
+        self.cash_from_sales.as_str()
+    }
+}
+
+/// Reporting date of one year before, "2022-09-30" -> "2021-09-30".
+pub fn previous_year_date(date: &str) -> Option<String> {
+    let year: i32 = date.get(0..4)?.parse().ok()?;
+    match date.get(4..)? {
+        "-02-29" => Some(format!("{}-02-28", year - 1)),
+        rest => Some(format!("{}{}", year - 1, rest)),
+    }
+}
+
+/// Reporting date of the quarter before, "2022-03-31" -> "2021-12-31".
+pub fn previous_quarter_date(date: &str) -> Option<String> {
+    let year: i32 = date.get(0..4)?.parse().ok()?;
+    match date.get(4..)? {
+        "-03-31" => Some(format!("{}-12-31", year - 1)),
+        "-06-30" => Some(format!("{}-03-31", year)),
+        "-09-30" => Some(format!("{}-06-30", year)),
+        "-12-31" => Some(format!("{}-09-30", year)),
+        _ => None,
+    }
+}
+
+pub fn change_ratio(current: f64, previous: f64) -> Option<f64> {
+    if previous == 0.0 {
+        None
+    } else {
+        Some((current - previous) / previous.abs())
+    }
+}
+
+fn find_period<'a, T: Statement>(data: &'a [T], date: &str) -> Option<&'a T> {
+    data.iter().find(|x| x.date() == date)
+}
+
+/// Value of the quarter alone, taking off the quarters before in cumulative statements.
+fn quarter_value<T: Statement>(data: &[T], item: &T, value: fn(&T) -> &str) -> Option<f64> {
+    let current = parse_value(value(item))?;
+    if !T::CUMULATIVE || item.date().ends_with("-03-31") {
+        return Some(current);
+    }
+    let previous = find_period(data, &previous_quarter_date(item.date())?)?;
+    Some(current - parse_value(value(previous))?)
+}
+
+/// Change of a row against the same period of last year, or the quarter before.
+pub fn period_change<T: Statement>(data: &[T], index: usize, value: fn(&T) -> &str, change: StatementChange) -> Option<f64> {
+    let item = data.get(index)?;
+    match change {
+        StatementChange::None => None,
+        StatementChange::YoY => {
+            let previous = find_period(data, &previous_year_date(item.date())?)?;
+            change_ratio(parse_value(value(item))?, parse_value(value(previous))?)
+        }
+        StatementChange::QoQ => {
+            let previous = find_period(data, &previous_quarter_date(item.date())?)?;
+            change_ratio(quarter_value(data, item, value)?, quarter_value(data, previous, value)?)
+        }
+    }
+}
+
+pub fn sort_periods<T: Statement>(data: &mut [T]) {
+    data.sort_by(|a, b| b.date().cmp(a.date()));
+}
+
+pub fn statement_grid<T: Statement>(ui: &mut Ui, id: &str, data: &[T], common_size: bool, change: StatementChange) {
+    ScrollArea::both().id_source(format!("{}-scroll", id)).show(ui, |ui| {
+        Grid::new(id)
+            .striped(true)
+            .spacing([20.0, 4.0])
+            .show(ui, |ui| {
+                ui.strong("报告期");
+                for item in data {
+                    ui.strong(item.date());
+                    match change {
+                        StatementChange::None => {}
+                        StatementChange::YoY => { ui.strong("同比"); }
+                        StatementChange::QoQ => { ui.strong("环比"); }
+                    }
+                }
+                ui.end_row();
+                for row in T::ROWS {
+                    ui.label(row.label);
+                    for (i, item) in data.iter().enumerate() {
+                        let value = parse_value((row.value)(item));
+                        let text = match value {
+                            Some(v) if common_size => match parse_value(item.base()) {
+                                Some(base) if base != 0.0 => format!("{:.2}%", v / base * 100.0),
+                                _ => "--".to_string(),
+                            },
+                            Some(v) => format_amount(v),
+                            None => "--".to_string(),
+                        };
+                        ui.label(text);
+                        if change != StatementChange::None {
+                            match period_change(data, i, row.value, change) {
+                                Some(r) => ui.label(RichText::new(format!("{:+.2}%", r * 100.0))
+                                    .color(if r >= 0.0 { Color32::RED } else { Color32::GREEN })),
+                                None => ui.label("--"),
+                            };
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+    });
+}
+
+impl StockView {
+    pub fn request_statements(&mut self, use_cache: bool) {
+        self.requesting_statements = true;
+        self.statement_error.clear();
+        let request = ViewRequest::Statements(self.stock.code.to_string());
+        if use_cache {
+            self.call(request);
+        } else {
+            self.call_uncached(request);
+        }
+    }
+    pub fn statements_ui(&mut self, ui: &mut Ui) {
+        if !self.requesting_statements && self.income_statement.is_none() {
+            self.request_statements(true);
+        }
+        let base_label = match self.tab {
+            StockViewTab::BalanceSheet => BalanceSheetItem::BASE_LABEL,
+            StockViewTab::CashFlowStatement => CashFlowStatementItem::BASE_LABEL,
+            _ => IncomeStatementItem::BASE_LABEL,
+        };
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.statement_common_size, format!("结构百分比（占{}）", base_label))
+                .on_hover_text(format!("以{}为 100%", base_label));
+            ComboBox::new(format!("{}-statement-change", self.stock.symbol), "变动")
+                .selected_text(match self.statement_change {
+                    StatementChange::None => "不显示",
+                    StatementChange::YoY => "同比",
+                    StatementChange::QoQ => "环比",
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.statement_change, StatementChange::None, "不显示");
+                    ui.selectable_value(&mut self.statement_change, StatementChange::YoY, "同比");
+                    ui.selectable_value(&mut self.statement_change, StatementChange::QoQ, "环比");
+                });
+            if ui.button("刷新").clicked() {
+                self.income_statement = None;
+                self.balance_sheet = None;
+                self.cash_flow_statement = None;
+                self.request_statements(false);
+            }
+            self.data_age_ui(ui, ViewRequestKind::Statements);
+        });
+        if !self.statement_error.is_empty() {
+            ui.label(RichText::new(format!("加载报表错误: {}", self.statement_error)).color(ui.visuals().warn_fg_color));
+        }
+        let id = format!("{}-{:?}", self.stock.symbol, self.tab);
+        let common_size = self.statement_common_size;
+        let change = self.statement_change;
+        let loaded = match self.tab {
+            StockViewTab::IncomeStatement => self.income_statement.as_ref()
+                .map(|x| statement_grid(ui, &id, &x.data, common_size, change)),
+            StockViewTab::BalanceSheet => self.balance_sheet.as_ref()
+                .map(|x| statement_grid(ui, &id, &x.data, common_size, change)),
+            StockViewTab::CashFlowStatement => self.cash_flow_statement.as_ref()
+                .map(|x| statement_grid(ui, &id, &x.data, common_size, change)),
+            _ => Some(()),
+        };
+        if loaded.is_none() {
+            ui.horizontal(|ui| {
+                ui.spinner();
+                ui.label("正在加载报表...");
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rpc::api::{BalanceSheetItem, IncomeStatementItem};
+    use crate::statements::{change_ratio, period_change, previous_quarter_date, previous_year_date, Statement, StatementChange};
+
+    #[test]
+    fn period_dates() {
+        let cases = [
+            ("2022-09-30", Some("2021-09-30"), Some("2022-06-30")),
+            ("2022-12-31", Some("2021-12-31"), Some("2022-09-30")),
+            ("2022-03-31", Some("2021-03-31"), Some("2021-12-31")),
+            ("2024-02-29", Some("2023-02-28"), None),
+            ("--", None, None),
+        ];
+        for (date, year, quarter) in cases {
+            assert_eq!(previous_year_date(date).as_deref(), year, "{}", date);
+            assert_eq!(previous_quarter_date(date).as_deref(), quarter, "{}", date);
+        }
+    }
+
+    #[test]
+    fn change_ratios() {
+        let cases = [
+            (110.0, 100.0, Some(0.1)),
+            (90.0, 100.0, Some(-0.1)),
+            // a loss narrowing is an increase
+            (-50.0, -100.0, Some(0.5)),
+            (50.0, -100.0, Some(1.5)),
+            (10.0, 0.0, None),
+        ];
+        for (current, previous, ratio) in cases {
+            let r = change_ratio(current, previous);
+            assert_eq!(r.is_some(), ratio.is_some());
+            if let (Some(r), Some(ratio)) = (r, ratio) {
+                assert!((r - ratio).abs() < 1e-9, "{} {}", current, previous);
+            }
+        }
+    }
+
+    #[test]
+    fn statement_changes() {
+        let income = |date: &str, revenue: &str| IncomeStatementItem { date: date.to_string(), revenue: revenue.to_string(), ..Default::default() };
+        // year-to-date revenue, quarters of 100, 150, 80 in 2022 and 100 in 2021 Q4
+        let data = [
+            income("2022-09-30", "330"),
+            income("2022-06-30", "250"),
+            income("2022-03-31", "100"),
+            income("2021-12-31", "400"),
+            income("2021-09-30", "300"),
+        ];
+        let revenue: fn(&IncomeStatementItem) -> &str = |x| x.revenue.as_str();
+        // (index, change, expected)
+        let cases = [
+            (0, StatementChange::QoQ, Some(80.0 / 150.0 - 1.0)),
+            (1, StatementChange::QoQ, Some(0.5)),
+            (2, StatementChange::QoQ, Some(0.0)),
+            // 2021-06-30 is missing
+            (4, StatementChange::QoQ, None),
+            (0, StatementChange::YoY, Some(0.1)),
+            (1, StatementChange::YoY, None),
+            (0, StatementChange::None, None),
+        ];
+        for (index, change, expected) in cases {
+            let r = period_change(&data, index, revenue, change);
+            assert_eq!(r.is_some(), expected.is_some(), "{} {:?}", index, change);
+            if let (Some(r), Some(expected)) = (r, expected) {
+                assert!((r - expected).abs() < 1e-9, "{} {:?}", index, change);
+            }
+        }
+        // balances compare as they are
+        let balance = |date: &str, total_assets: &str| BalanceSheetItem { date: date.to_string(), total_assets: total_assets.to_string(), ..Default::default() };
+        let data = [balance("2022-06-30", "120"), balance("2022-03-31", "100")];
+        let r = period_change(&data, 0, |x| x.total_assets.as_str(), StatementChange::QoQ).unwrap();
+        assert!((r - 0.2).abs() < 1e-9);
+        assert!(!BalanceSheetItem::CUMULATIVE && IncomeStatementItem::CUMULATIVE);
+    }
+}
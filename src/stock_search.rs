@@ -0,0 +1,287 @@
+use egui::{Color32, ComboBox, Label, RichText, Ui};
+use regex::Regex;
+use rpc::api::{StockListRequest, StockListResp, StockResp, StockStatus};
+use tracing::{info, warn};
+use crate::constants::{SEARCH_DEBOUNCE_SECONDS, STOCK_LIST_PAGE_SIZE};
+use crate::financial_analysis::FinancialAnalysis;
+use crate::offline::data_age_ui;
+use crate::server_info::FEATURE_STOCK_SEARCH;
+use crate::service::ServiceRequest;
+use crate::utils::{get_random_u32, now_millis};
+
+pub const MARKETS: [(&str, &str); 4] = [("", "全部"), ("SH", "上交所"), ("SZ", "深交所"), ("BJ", "北交所")];
+pub const BOARDS: [(&str, &str); 5] = [("", "全部"), ("main", "主板"), ("chinext", "创业板"), ("star", "科创板"), ("bse", "北证")];
+pub const STATUSES: [(Option<StockStatus>, &str); 4] = [
+    (None, "全部"),
+    (Some(StockStatus::Normal), "正常"),
+    (Some(StockStatus::St), "ST"),
+    (Some(StockStatus::Delisted), "退市"),
+];
+
+/// Display name of `value` in `names`, the value itself when unknown.
+pub fn name_of<'a>(names: &[(&str, &'a str)], value: &'a str) -> &'a str {
+    names.iter().find(|(x, _)| *x == value).map(|(_, name)| *name).unwrap_or(value)
+}
+
+pub fn status_text(ui: &Ui, status: StockStatus) -> RichText {
+    let name = STATUSES.iter().find(|(x, _)| *x == Some(status)).map(|(_, name)| *name).unwrap_or("-");
+    match status {
+        StockStatus::Normal => RichText::new(name),
+        StockStatus::St => RichText::new(name).color(ui.visuals().warn_fg_color),
+        StockStatus::Delisted => RichText::new(name).color(Color32::GRAY).strikethrough(),
+    }
+}
+
+/// Request of the whole list, what's loaded from servers without search.
+pub fn unfiltered(request: &StockListRequest) -> bool {
+    request.query.is_empty() && request.market.is_empty() && request.industry.is_empty() && request.board.is_empty()
+        && request.statuses.is_empty() && request.page_token.is_empty()
+}
+
+/// State of searching stocks on server, results are in `stock_list_select`.
+#[derive(Debug, Default)]
+pub struct StockSearch {
+    /// results are from server side search, otherwise filtered locally from the whole list
+    pub server_side: bool,
+    pub market: String,
+    pub board: String,
+    pub industry: String,
+    pub status: Option<StockStatus>,
+    /// bumped on every request to drop responses of outdated ones
+    generation: u64,
+    next_page_token: String,
+    pub total: u32,
+    /// `now_millis` of last edit not searched yet
+    edited_at: Option<f64>,
+}
+
+impl StockSearch {
+    /// Forget results, keeping the generation so late responses are still dropped.
+    pub fn reset(&mut self) {
+        *self = Self { generation: self.generation, ..Default::default() };
+    }
+    /// Whether `stock` passes the filters other than query, for filtering locally.
+    pub fn matches(&self, stock: &StockResp) -> bool {
+        let industry = self.industry.trim();
+        (self.market.is_empty() || stock.exchange == self.market)
+            && (self.board.is_empty() || stock.board == self.board)
+            && (industry.is_empty() || stock.industry.contains(industry))
+            && self.status.map_or(true, |x| stock.status() == x)
+    }
+}
+
+impl FinancialAnalysis {
+    pub fn server_side_search(&self) -> bool {
+        // offline, what's cached is filtered locally
+        !self.offline && self.server_info.advertises(FEATURE_STOCK_SEARCH)
+    }
+    pub fn load_stock_list(&mut self) {
+        // offline, the service answers from cache
+        if !self.connected() && !self.offline {
+            warn!("no client when updating stock!");
+            return;
+        }
+        let server_side = self.server_side_search();
+        if server_side != self.search.server_side {
+            self.stock_list_select.clear();
+            self.stock_list_select_text.clear();
+        }
+        self.search.server_side = server_side;
+        if server_side {
+            self.search_stocks(false);
+        } else {
+            self.request_stock_list(Default::default());
+        }
+    }
+    /// Search with current inputs, or load the next page of shown results when `more`.
+    pub fn search_stocks(&mut self, more: bool) {
+        let request = StockListRequest {
+            query: self.search_text.trim().to_string(),
+            market: self.search.market.to_string(),
+            industry: self.search.industry.trim().to_string(),
+            page_token: if more { self.search.next_page_token.to_string() } else { "".to_string() },
+            page_size: STOCK_LIST_PAGE_SIZE,
+            board: self.search.board.to_string(),
+            statuses: self.search.status.map(|x| vec![x as i32]).unwrap_or_default(),
+        };
+        self.request_stock_list(request);
+    }
+    fn request_stock_list(&mut self, request: StockListRequest) {
+        self.search.generation += 1;
+        self.stock_list_requesting = true;
+        self.call(ServiceRequest::StockList((self.search.generation, request)));
+    }
+    pub fn on_stock_list(&mut self, generation: u64, request: StockListRequest, resp: StockListResp, time: u64) {
+        if generation != self.search.generation {
+            return;
+        }
+        info!("got stock list {}/{}", resp.data.len(), resp.total);
+        self.stock_list_time = Some(time);
+        self.stock_list_requesting = false;
+        self.stock_list_error.clear();
+        self.search.next_page_token = resp.next_page_token;
+        self.search.total = resp.total.max(resp.data.len() as u32);
+        if unfiltered(&request) {
+            self.stock_list = resp.data.clone();
+            self.pick_popular();
+        }
+        if self.search.server_side {
+            if request.page_token.is_empty() {
+                self.stock_list_select = resp.data;
+            } else {
+                self.stock_list_select.extend(resp.data);
+            }
+            self.stock_list_select_text = request.query;
+        }
+    }
+    pub fn on_stock_list_error(&mut self, generation: u64, error: String) {
+        if generation != self.search.generation {
+            return;
+        }
+        warn!("load stock list failed: {}", error);
+        self.stock_list_requesting = false;
+        self.stock_list_error = error;
+    }
+    /// Randomly select some stocks to popular.
+    fn pick_popular(&mut self) {
+        if self.stock_list.len() > 0 {
+            let n = 6;
+            let listed: Vec<&StockResp> = self.stock_list.iter().filter(|x| x.status() != StockStatus::Delisted).collect();
+            let mut data = vec![];
+            for _ in 0..n {
+                if let Some(stock) = listed.get(get_random_u32() as usize % listed.len().max(1)) {
+                    data.push((*stock).clone());
+                }
+            }
+            self.stock_list_popular = data;
+        }
+    }
+    /// Called every frame, searches once typing pauses.
+    pub fn check_stock_search(&mut self) {
+        if let Some(edited_at) = self.search.edited_at {
+            if now_millis() - edited_at >= SEARCH_DEBOUNCE_SECONDS * 1e3 {
+                self.search.edited_at = None;
+                self.search_stocks(false);
+            }
+        }
+    }
+    pub fn search_ui(&mut self, ui: &mut Ui) {
+        ui.label("🔍搜索");
+        if self.search.server_side {
+            self.server_search_ui(ui);
+        } else {
+            self.local_search_ui(ui);
+        }
+        data_age_ui(ui, self.stock_list_time);
+        if !self.stock_list_error.is_empty() {
+            ui.label(RichText::new(format!("加载股票列表失败：{}", self.stock_list_error)).color(ui.visuals().warn_fg_color));
+            if ui.button("重试").clicked() {
+                self.stock_list_error.clear();
+                self.stock_list_time = None;
+            }
+        }
+    }
+    /// Filters other than query, returns whether any changed.
+    fn filter_ui(&mut self, ui: &mut Ui) -> bool {
+        let (market, board, status) = (self.search.market.to_string(), self.search.board.to_string(), self.search.status);
+        ComboBox::new("search-market", "市场")
+            .selected_text(name_of(&MARKETS, &market))
+            .show_ui(ui, |ui| {
+                for (value, name) in MARKETS {
+                    ui.selectable_value(&mut self.search.market, value.to_string(), name);
+                }
+            });
+        ComboBox::new("search-board", "板块")
+            .selected_text(name_of(&BOARDS, &board))
+            .show_ui(ui, |ui| {
+                for (value, name) in BOARDS {
+                    ui.selectable_value(&mut self.search.board, value.to_string(), name);
+                }
+            });
+        ComboBox::new("search-status", "状态")
+            .selected_text(STATUSES.iter().find(|(x, _)| *x == status).map(|(_, name)| *name).unwrap_or("全部"))
+            .show_ui(ui, |ui| {
+                for (value, name) in STATUSES {
+                    ui.selectable_value(&mut self.search.status, value, name);
+                }
+            });
+        ui.label("行业");
+        let industry = ui.add(egui::TextEdit::singleline(&mut self.search.industry).desired_width(80.0)).changed();
+        industry || market != self.search.market || board != self.search.board || status != self.search.status
+    }
+    fn server_search_ui(&mut self, ui: &mut Ui) {
+        let mut edited = ui.text_edit_singleline(&mut self.search_text).changed();
+        edited |= self.filter_ui(ui);
+        if edited {
+            self.search.edited_at = Some(now_millis());
+        }
+        if self.stock_list_requesting {
+            ui.spinner();
+        }
+        ui.label(RichText::new(format!("共 {} 只，已显示 {} 只", self.search.total, self.stock_list_select.len()))
+            .color(ui.visuals().weak_text_color()));
+        if !self.search.next_page_token.is_empty() && ui.add_enabled(!self.stock_list_requesting, egui::Button::new("加载更多")).clicked() {
+            self.search_stocks(true);
+        }
+    }
+    /// Filter the whole list with a regex, for servers without search.
+    fn local_search_ui(&mut self, ui: &mut Ui) {
+        let re = Regex::new(self.search_text.as_str());
+        let edited = ui.text_edit_singleline(&mut self.search_text).changed();
+        let filtered = self.filter_ui(ui);
+        if !self.stock_list.is_empty() && (edited || filtered || self.search_text != self.stock_list_select_text) {
+            let filter =
+                if let Ok(re) = re.clone() {
+                    let re = re.clone();
+                    Some(move |text: &str| {
+                        re.is_match(text)
+                    })
+                } else { None };
+            let stock_list_select = self.stock_list.iter().filter(|s| self.search.matches(s)).filter(|s| {
+                if let Some(filter) = &filter {
+                    filter(&s.code) ||
+                        filter(&s.symbol) ||
+                        filter(&s.name)
+                } else {
+                    let filter = |text: &str| self.search_text.contains(text);
+                    filter(&s.code) ||
+                        filter(&s.symbol) ||
+                        filter(&s.name)
+                }
+            });
+            self.stock_list_select = stock_list_select.map(|x| x.clone()).collect();
+            self.stock_list_select_text = self.search_text.to_string();
+        }
+        ui.add(Label::new(RichText::new(if self.search_text.is_empty() {
+            "支持正则表达式检索"
+        } else {
+            if re.is_ok() {
+                "正确的正则表达式"
+            } else {
+                "无效的正则表达式"
+            }
+        }).color(ui.visuals().weak_text_color())));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rpc::api::{StockResp, StockStatus};
+    use crate::stock_search::StockSearch;
+
+    #[test]
+    fn match_filters() {
+        let stock = StockResp { exchange: "SZ".to_string(), board: "chinext".to_string(), industry: "电池".to_string(), status: StockStatus::St as i32, ..Default::default() };
+        let mut search = StockSearch::default();
+        assert!(search.matches(&stock));
+        search.market = "SZ".to_string();
+        search.industry = " 电池 ".to_string();
+        search.status = Some(StockStatus::St);
+        assert!(search.matches(&stock));
+        search.board = "main".to_string();
+        assert!(!search.matches(&stock));
+        search.board.clear();
+        search.status = Some(StockStatus::Normal);
+        assert!(!search.matches(&stock));
+    }
+}
@@ -1,13 +1,20 @@
+use std::collections::{HashMap, HashSet};
 use std::ops::RangeInclusive;
-use std::sync::mpsc;
 use eframe::emath::Align;
 use egui::{Align2, CentralPanel, Color32, ComboBox, DragValue, FontId, Grid, Label, Layout, Painter, pos2, Rect, RichText, ScrollArea, Sense, TopBottomPanel, Ui, vec2, Widget, Window};
-use rpc::api::{GuideLineRequest, GuideLineResp, IncomeAnalysisRequest, IncomeAnalysisResp, PredictRequest, StockIssueRequest, StockIssueResp, StockResp, TradingHistoryItem, TradingHistoryRequest, TradingHistoryType};
-use tracing::{error, info};
+use rpc::api::{BalanceSheetResp, CashFlowStatementResp, GuideLineResp, IncomeAnalysisResp, IncomeStatementResp, Quote, ShareIndex, StockIssueResp, StockOverviewRequest, StockResp, TradingHistoryItem, TradingHistoryType};
+use tracing::info;
 use crate::constants::LINE_WIDTH;
-use crate::financial_analysis::MainApiClient;
-use crate::message::Message;
-use crate::utils::{execute, get_text_size};
+use crate::income::INCOME_YEARS_DEFAULT;
+use crate::issue::StockIssue;
+use crate::message::{RequestId, RequestTag, ViewId, ViewRequestKind, ViewResponse};
+use crate::offline::data_age_ui;
+use crate::statements::{sort_periods, StatementChange};
+use crate::server_info::{ServerInfo, ALL_TIMEFRAMES, FEATURE_GUIDE_LINE, FEATURE_INCOME_ANALYSIS, FEATURE_PREDICT, FEATURE_STATEMENTS, FEATURE_STOCK_ISSUE, FEATURE_STOCK_OVERVIEW};
+use crate::request::{cancel_pair, CancelGuard, CancelToken};
+use crate::service::{ServiceHandle, ServiceRequest, ViewRequest};
+use crate::utils::get_text_size;
+use crate::valuation::{merge_share_index, ValuationBand, ValuationCache};
 
 #[derive(Debug, Clone)]
 pub struct TradingHistoryValueItem {
@@ -32,6 +39,18 @@ impl From<TradingHistoryItem> for TradingHistoryValueItem {
     }
 }
 
+/// Merge `bars` fetched since some date into `data`, both sorted by date.
+///
+/// Bars of `data` from the first new date on are replaced, the last one may have
+/// been incomplete when fetched.
+pub fn merge_history<T>(data: &mut Vec<T>, bars: Vec<T>, date: impl Fn(&T) -> &str) {
+    if let Some(first) = bars.first() {
+        let keep = data.partition_point(|x| date(x) < date(first));
+        data.truncate(keep);
+        data.extend(bars);
+    }
+}
+
 impl TradingHistoryValueItem {
     pub fn valid(&self) -> bool {
         self.volume != 0 && self.low > 0.0 && self.open > 0.0 && self.close > 0.0 && self.high > 0.0
@@ -56,12 +75,22 @@ impl TradingHistoryValueItem {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StockViewTab {
+    Overview,
+    IncomeStatement,
+    BalanceSheet,
+    CashFlowStatement,
+    Valuation,
+    Scoring,
+}
+
 pub struct StockView {
+    pub id: ViewId,
     pub stock: StockResp,
     pub data: Vec<TradingHistoryValueItem>,
-    pub client: Option<MainApiClient>,
-    pub tx: Option<mpsc::Sender<Message>>,
-    requesting: bool,
+    pub service: Option<ServiceHandle>,
+    pub requesting: bool,
     error: String,
     pub typ: TradingHistoryType,
     pub valid: bool,
@@ -71,25 +100,63 @@ pub struct StockView {
     predict_error: String,
 
     pub issue: Option<StockIssueResp>,
+    pub issue_detail: Option<StockIssue>,
     requesting_issue: bool,
+    issue_error: String,
 
     pub guide_line: Option<GuideLineResp>,
     pub guide_line_year: usize,
     requesting_guide_line: bool,
     guide_line_error: String,
+    /// share indexes of every loaded guide line, newest first
+    pub share_index: Vec<ShareIndex>,
+    /// years of guide lines answered for valuation
+    pub share_index_years: HashSet<i32>,
+    /// year of the guide line requested for valuation
+    pub share_index_pending: Option<i32>,
+    pub valuation_cache: ValuationCache,
 
     pub income_analysis: Option<IncomeAnalysisResp>,
-    requesting_income_analysis: bool,
+    pub income_years: u32,
+    pub requesting_income_analysis: bool,
+    pub income_analysis_error: String,
+
+    pub tab: StockViewTab,
+    pub income_statement: Option<IncomeStatementResp>,
+    pub balance_sheet: Option<BalanceSheetResp>,
+    pub cash_flow_statement: Option<CashFlowStatementResp>,
+    pub requesting_statements: bool,
+    pub statement_error: String,
+    pub statement_common_size: bool,
+    pub statement_change: StatementChange,
+    pub valuation_band: ValuationBand,
+
+    pub server_info: ServerInfo,
+    pub predict_model: String,
+    /// in-flight requests are dropped with this guard when the window closes
+    _cancel_guard: CancelGuard,
+    cancel: CancelToken,
+    next_request: RequestId,
+    /// latest request of each kind, older responses are stale
+    latest_requests: HashMap<ViewRequestKind, RequestId>,
+    /// time data of each kind was fetched from server
+    fetched_at: HashMap<ViewRequestKind, u64>,
+    /// showing cached data only, server side computing is unavailable
+    pub offline: bool,
+    pub quote: Option<Quote>,
+    /// `now_millis` of last quote update, for flashing
+    pub quote_updated_at: f64,
 }
 
 impl StockView {
-    pub fn new(stock: StockResp, client: Option<MainApiClient>, tx: Option<mpsc::Sender<Message>>) -> Self {
+    pub fn new(id: ViewId, stock: StockResp, service: Option<ServiceHandle>) -> Self {
+        let (cancel_guard, cancel) = cancel_pair();
         Self {
+            id,
             stock,
             data: vec![],
-            client,
+            service,
             requesting: false,
-            tx,
             typ: TradingHistoryType::Week,
             error: "".to_string(),
             valid: true,
@@ -98,81 +165,121 @@ impl StockView {
             predicting: false,
             predict_error: "".to_string(),
             issue: None,
+            issue_detail: None,
             requesting_issue: false,
+            issue_error: "".to_string(),
             guide_line: None,
             guide_line_year: 2022,
             requesting_guide_line: false,
             guide_line_error: "".to_string(),
+            share_index: vec![],
+            share_index_years: HashSet::new(),
+            share_index_pending: None,
+            valuation_cache: Default::default(),
             income_analysis: None,
+            income_years: INCOME_YEARS_DEFAULT,
             requesting_income_analysis: false,
+            income_analysis_error: "".to_string(),
+            tab: StockViewTab::Overview,
+            income_statement: None,
+            balance_sheet: None,
+            cash_flow_statement: None,
+            requesting_statements: false,
+            statement_error: "".to_string(),
+            statement_common_size: false,
+            statement_change: StatementChange::None,
+            valuation_band: ValuationBand::None,
+            server_info: Default::default(),
+            predict_model: "".to_string(),
+            _cancel_guard: cancel_guard,
+            cancel,
+            next_request: 0,
+            latest_requests: HashMap::new(),
+            fetched_at: HashMap::new(),
+            offline: false,
+            quote: None,
+            quote_updated_at: 0.0,
         }
     }
-    pub fn window(&mut self, ctx: &egui::Context) {
-        if self.issue.is_none() && !self.requesting_issue {
+    /// Send a request to service, dropped when this view closes.
+    ///
+    /// Supersedes earlier requests of the same kind, their responses are ignored.
+    pub fn call(&mut self, request: ViewRequest) {
+        self.send(request, true);
+    }
+    /// Like [`Self::call`], but always fetches from server.
+    pub fn call_uncached(&mut self, request: ViewRequest) {
+        self.send(request, false);
+    }
+    fn send(&mut self, request: ViewRequest, use_cache: bool) {
+        self.next_request += 1;
+        for kind in request.kinds() {
+            self.latest_requests.insert(kind, self.next_request);
+        }
+        let tag = RequestTag { view: self.id, request: self.next_request };
+        if let Some(service) = &self.service {
+            service.send_cancellable(ServiceRequest::View((tag, request)), &self.cancel, use_cache);
+        }
+    }
+    /// Adapt options to what the server supports.
+    pub fn set_server_info(&mut self, server_info: ServerInfo) {
+        if !server_info.supports_timeframe(self.typ) {
+            if let Some(typ) = server_info.timeframes.first() {
+                self.typ = *typ;
+                self.requesting = false;
+                self.error.clear();
+                self.data.clear();
+            }
+        }
+        if !server_info.predict_models.contains(&self.predict_model) {
+            self.predict_model = server_info.predict_models.first().cloned().unwrap_or_default();
+        }
+        if !server_info.supports(FEATURE_STATEMENTS) {
+            self.tab = StockViewTab::Overview;
+        }
+        self.server_info = server_info;
+    }
+    /// Request what the first screen misses, in one round trip when the server supports it.
+    fn request_missing(&mut self) {
+        let issue = self.issue.is_none() && !self.requesting_issue && self.issue_error.is_empty() && self.server_info.supports(FEATURE_STOCK_ISSUE);
+        let income_analysis = self.income_analysis.is_none() && !self.requesting_income_analysis && self.income_analysis_error.is_empty() && self.server_info.supports(FEATURE_INCOME_ANALYSIS);
+        let history = !self.requesting && self.data.is_empty() && self.error.is_empty();
+        // offline, parts are looked up in cache one by one
+        if history && !self.offline && self.server_info.advertises(FEATURE_STOCK_OVERVIEW) {
+            self.requesting = true;
+            self.requesting_issue |= issue;
+            self.requesting_income_analysis |= income_analysis;
+            self.call(ViewRequest::Overview(StockOverviewRequest {
+                symbol: self.stock.symbol.to_string(),
+                code: self.stock.code.to_string(),
+                typ: self.typ as i32,
+                since: "".to_string(),
+                issue,
+                income_analysis,
+                income_years: self.income_years,
+            }));
+            return;
+        }
+        if issue {
             self.requesting_issue = true;
-            let code = self.stock.code.to_string();
-            let client = self.client.clone();
-            let tx = self.tx.clone();
-            execute(async move {
-                if let Some(mut client) = client {
-                    if let Some(tx) = tx {
-                        let r = client.stock_issue(StockIssueRequest { symbol: code.clone() }).await;
-                        if let Ok(r) = r {
-                            let data = r.into_inner();
-                            tx.send(Message::GotStockIssue((code, data, "".to_string()))).unwrap();
-                        }
-                    }
-                }
-            });
+            self.call(ViewRequest::StockIssue(self.stock.code.to_string()));
         }
-        if self.income_analysis.is_none() && !self.requesting_income_analysis {
-            self.requesting_income_analysis = true;
-            let code = self.stock.code.to_string();
-            let client = self.client.clone();
-            let tx = self.tx.clone();
-            execute(async move {
-                if let Some(mut client) = client {
-                    if let Some(tx) = tx {
-                        let r = client.income_analysis(IncomeAnalysisRequest { code: code.clone() }).await;
-                        if let Ok(r) = r {
-                            let data = r.into_inner();
-                            tx.send(Message::GotIncomeAnalysis((code, data))).unwrap();
-                        }
-                    }
-                }
-            });
+        if income_analysis {
+            self.request_income_analysis();
         }
-        if !self.requesting && self.data.is_empty() && self.error.is_empty() {
+        if history {
             self.requesting = true;
-            let symbol = self.stock.symbol.to_string();
-            let typ = match self.typ {
-                TradingHistoryType::Daily => 0,
-                TradingHistoryType::Week => 1,
-                TradingHistoryType::Month => 2,
-            };
-            let mut client = self.client.clone();
-            let tx = self.tx.clone();
-            execute(async move {
-                if let Some(tx) = tx {
-                    if let Some(client) = &mut client {
-                        let r = client.trading_history(TradingHistoryRequest { symbol: symbol.clone(), typ }).await;
-                        match r {
-                            Ok(r) => {
-                                let resp = r.into_inner();
-                                info!("get trading history done: {}", symbol);
-                                tx.send(Message::GotTradingHistory((symbol, resp.data, "".to_string()))).unwrap();
-                            }
-                            Err(e) => {
-                                error!("{}", e);
-                                tx.send(Message::GotTradingHistory((symbol, vec![], e.to_string()))).unwrap();
-                            }
-                        }
-                    }
-                }
-            });
+            self.call(ViewRequest::TradingHistory((self.stock.symbol.to_string(), self.typ, "".to_string())));
         }
+    }
+    pub fn window(&mut self, ctx: &egui::Context) {
+        self.request_missing();
         let mut valid = self.valid;
-        Window::new(format!("[{}]{}", self.stock.code, self.stock.name))
+        if self.flashing() {
+            ctx.request_repaint();
+        }
+        Window::new(self.title())
+            .id(egui::Id::new(("stock-view", self.id)))
             .open(&mut valid)
             .min_width(32.0)
             .min_height(64.0)
@@ -199,9 +306,13 @@ impl StockView {
                                     .show_ui(ui, |ui| {
                                         ui.style_mut().wrap = Some(false);
                                         ui.set_min_width(60.0);
-                                        ui.selectable_value(&mut self.typ, TradingHistoryType::Daily, "日线");
-                                        ui.selectable_value(&mut self.typ, TradingHistoryType::Week, "周线");
-                                        ui.selectable_value(&mut self.typ, TradingHistoryType::Month, "月线");
+                                        for typ in ALL_TIMEFRAMES.into_iter().filter(|x| self.server_info.supports_timeframe(*x)) {
+                                            ui.selectable_value(&mut self.typ, typ, match typ {
+                                                TradingHistoryType::Daily => "日线",
+                                                TradingHistoryType::Week => "周线",
+                                                TradingHistoryType::Month => "月线",
+                                            });
+                                        }
                                     });
                                 if type_last != self.typ {
                                     // change request option, reload
@@ -209,102 +320,81 @@ impl StockView {
                                     self.error.clear();
                                     self.data.clear();
                                 }
+                                let predict_supported = self.server_info.supports(FEATURE_PREDICT) && !self.offline;
+                                if predict_supported && !self.server_info.predict_models.is_empty() {
+                                    ComboBox::new(format!("{}-predict-model", self.stock.symbol), "预测模型")
+                                        .selected_text(self.predict_model.as_str())
+                                        .show_ui(ui, |ui| {
+                                            ui.style_mut().wrap = Some(false);
+                                            for model in &self.server_info.predict_models {
+                                                ui.selectable_value(&mut self.predict_model, model.to_string(), model.as_str());
+                                            }
+                                        });
+                                }
                                 ui.label("预测新数据范围");
-                                ui.add_enabled_ui(!self.predicting, |ui| {
+                                ui.add_enabled_ui(!self.predicting && predict_supported, |ui| {
                                     DragValue::new(&mut self.predict_len)
                                         .clamp_range(0..=(self.data.len() / 4))
                                         .ui(ui);
                                     ui.add_enabled_ui(self.predict_len != 0, |ui| {
                                         if ui.button(if self.predicting { "正在预测" } else { "预测" }).clicked() {
-                                            let tx = self.tx.clone();
-                                            let client = self.client.clone();
-                                            let length = self.predict_len;
                                             self.predicting = true;
-                                            let raw_data = self.data.clone();
-                                            let symbol = self.stock.symbol.to_string();
-                                            execute(async move {
-                                                if let Some(tx) = tx {
-                                                    if let Some(client) = &client {
-                                                        let clients = (0..4).map(|_| client.clone()).collect::<Vec<_>>();
-                                                        let data_list: Vec<Vec<f32>> = vec![
-                                                            raw_data.iter().map(|x| x.high).collect(),
-                                                            raw_data.iter().map(|x| x.low).collect(),
-                                                            raw_data.iter().map(|x| x.open).collect(),
-                                                            raw_data.iter().map(|x| x.close).collect(),
-                                                        ];
-                                                        let mut results: Vec<Vec<f32>> = (0..4).map(|_| vec![]).collect();
-                                                        let mut errors = vec![];
-                                                        let mut features = vec![];
-                                                        for (i, (data, mut client)) in data_list.into_iter().zip(clients.into_iter()).enumerate() {
-                                                            info!("requesting new predict... {}/4", i);
-                                                            let r = async move {
-                                                                client.predict_data(PredictRequest { data, length }).await
-                                                            };
-                                                            features.push((i, r));
-                                                        }
-                                                        #[cfg(not(target_arch = "wasm32"))]
-                                                            let features = features.into_iter().map(|r|
-                                                            (r.0, tokio::spawn(r.1))
-                                                        ).collect::<Vec<_>>();
-                                                        #[cfg(not(target_arch = "wasm32"))]
-                                                        for (i, r) in features {
-                                                            let r = r.await;
-                                                            match r {
-                                                                Ok(r) => match r {
-                                                                    Ok(r) => {
-                                                                        results[i] = r.into_inner().data;
-                                                                    }
-                                                                    Err(e) => {
-                                                                        errors.push(e.to_string())
-                                                                    }
-                                                                },
-                                                                Err(e) => errors.push(e.to_string()),
-                                                            }
-                                                        }
-                                                        #[cfg(target_arch = "wasm32")]
-                                                        for (i, r) in features {
-                                                            let r = r.await;
-                                                            match r {
-                                                                Ok(r) =>  {
-                                                                    results[i] = r.into_inner().data;
-                                                                },
-                                                                Err(e) => errors.push(e.to_string()),
-                                                            }
-                                                        }
-                                                        let len = results.iter().map(|x| x.len()).min();
-                                                        if results.len() != 4 || len.is_none() || len.unwrap_or(0) == 0 {
-                                                            let e = format!("Errors: {:?}", errors);
-                                                            error!("{}", e);
-                                                            tx.send(Message::GotPredicts((symbol, vec![], e))).unwrap();
-                                                        } else {
-                                                            info!("got {:?} predicts", len);
-                                                            let mut predicts = vec![];
-                                                            for i in 0..len.unwrap() {
-                                                                let mut p = TradingHistoryValueItem::new("");
-                                                                p.high = results[0][i];
-                                                                p.low = results[1][i];
-                                                                p.open = results[2][i];
-                                                                p.close = results[3][i];
-                                                                p.volume = 1;
-                                                                predicts.push(p);
-                                                            }
-                                                            tx.send(Message::GotPredicts((symbol, predicts, "".to_string()))).unwrap();
-                                                        }
-                                                    }
-                                                }
-                                            });
+                                            self.call(ViewRequest::Predict((
+                                                self.stock.symbol.to_string(),
+                                                self.data.clone(),
+                                                self.predict_len,
+                                                self.predict_model.to_string(),
+                                            )));
                                         }
                                         if self.predicting {
                                             ui.spinner();
                                         }
                                     });
                                 });
+                                if ui.button("刷新").on_hover_text("只获取最新的 K 线").clicked() {
+                                    self.refresh_history();
+                                }
+                                self.data_age_ui(ui, ViewRequestKind::TradingHistory);
+                                if !self.error.is_empty() && !self.data.is_empty() {
+                                    ui.label(RichText::new(format!("刷新失败：{}", self.error)).color(ui.visuals().warn_fg_color));
+                                }
                             });
                         }
                     });
                 TopBottomPanel::bottom(format!("{}-history", self.stock.symbol))
                     .resizable(false)
                     .show_inside(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.selectable_value(&mut self.tab, StockViewTab::Overview, "概览");
+                            // statements and the scores built from them
+                            ui.add_enabled_ui(self.server_info.supports(FEATURE_STATEMENTS), |ui| {
+                                ui.selectable_value(&mut self.tab, StockViewTab::IncomeStatement, "利润表");
+                                ui.selectable_value(&mut self.tab, StockViewTab::BalanceSheet, "资产负债表");
+                                ui.selectable_value(&mut self.tab, StockViewTab::CashFlowStatement, "现金流量表");
+                            });
+                            // per-share figures come from guide lines
+                            ui.add_enabled_ui(self.server_info.supports(FEATURE_GUIDE_LINE), |ui| {
+                                ui.selectable_value(&mut self.tab, StockViewTab::Valuation, "估值");
+                            });
+                            ui.add_enabled_ui(self.server_info.supports(FEATURE_STATEMENTS), |ui| {
+                                ui.selectable_value(&mut self.tab, StockViewTab::Scoring, "评分");
+                            });
+                        });
+                        match self.tab {
+                            StockViewTab::Overview => {}
+                            StockViewTab::Valuation => {
+                                self.valuation_ui(ui);
+                                return;
+                            }
+                            StockViewTab::Scoring => {
+                                self.scoring_ui(ui);
+                                return;
+                            }
+                            _ => {
+                                self.statements_ui(ui);
+                                return;
+                            }
+                        }
                         ui.with_layout(Layout::left_to_right(Align::Min).with_cross_justify(true), |ui| {
                             Grid::new(format!("{}-info-grid", self.stock.symbol))
                                 .num_columns(2)
@@ -363,64 +453,36 @@ impl StockView {
                                         ui.label("上市日期");
                                         ui.label(issue.launch_date.as_str());
                                         ui.end_row();
+                                        ui.label("数据时间");
+                                        self.data_age_ui(ui, ViewRequestKind::StockIssue);
+                                        ui.end_row();
+                                    } else if !self.server_info.supports(FEATURE_STOCK_ISSUE) {
+                                        ui.label("服务器不支持发行信息");
+                                    } else if !self.issue_error.is_empty() {
+                                        ui.label(RichText::new(format!("加载股票信息失败：{}", self.issue_error)).color(ui.visuals().warn_fg_color));
                                     } else {
                                         ui.spinner();
                                         ui.label("正在加载股票信息...");
                                     }
                                 });
-                            Grid::new(format!("{}-incomes-grid", self.stock.symbol))
-                                .num_columns(2)
-                                .spacing([40.0, 4.0])
-                                .striped(true)
-                                .show(ui, |ui| {
-                                    ui.strong("三年营业收入");
-                                    ui.end_row();
-                                    ui.label("2022");
-                                    ui.label("2021");
-                                    ui.label("2020");
-                                    ui.end_row();
-                                    if let Some(income_analysis) = &self.income_analysis {
-                                        for i in 0..3 {
-                                            if let Some(v) = income_analysis.incomes.get(i) {
-                                                ui.label(format!("{}", v));
-                                            } else {
-                                                ui.label("数据无效");
-                                            }
-                                        }
-                                        ui.end_row();
-                                        ui.strong("平均营收比");
-                                        ui.label(format!("{}", income_analysis.ave));
-                                        ui.end_row();
-                                    }
-                                });
+                            self.issue_metrics_ui(ui);
+                            if self.server_info.supports(FEATURE_INCOME_ANALYSIS) {
+                                self.income_analysis_ui(ui);
+                            }
                             ui.vertical(|ui| {
-                                ui.add_enabled_ui(!self.requesting_guide_line, |ui| {
+                                ui.add_enabled_ui(!self.requesting_guide_line && self.server_info.supports(FEATURE_GUIDE_LINE), |ui| {
                                     ui.horizontal(|ui| {
                                         DragValue::new(&mut self.guide_line_year)
                                             .clamp_range(2000..=2023)
                                             .ui(ui);
                                         if ui.button(format!("获取{}年财务数据", self.guide_line_year)).clicked() {
                                             self.requesting_guide_line = true;
-                                            let code = self.stock.code.to_string();
-                                            let year = self.guide_line_year.to_string();
-                                            let client = self.client.clone();
-                                            let tx = self.tx.clone();
-                                            execute(async move {
-                                                if let Some(mut client) = client {
-                                                    if let Some(tx) = tx {
-                                                        let r = client.guide_line(GuideLineRequest { code: code.clone(), year }).await;
-                                                        match r {
-                                                            Ok(r) => {
-                                                                let data = r.into_inner();
-                                                                tx.send(Message::GotGuideLine((code, data, "".to_string()))).unwrap();
-                                                            }
-                                                            Err(e) => {
-                                                                tx.send(Message::GotGuideLine((code, Default::default(), e.to_string()))).unwrap();
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            });
+                                            // supersedes the one of valuation, fetched again later
+                                            self.share_index_pending = None;
+                                            self.call(ViewRequest::GuideLine((self.stock.code.to_string(), self.guide_line_year.to_string())));
+                                        }
+                                        if self.guide_line.is_some() {
+                                            self.data_age_ui(ui, ViewRequestKind::GuideLine);
                                         }
                                     });
                                 });
@@ -577,47 +639,178 @@ impl StockView {
                 last_date_rect = Some(date_rect);
             }
         }
+        self.paint_valuation_bands(&painter, rect_data_max, |i, price| pos2(
+            rect_data_max.left() + (i as f32 + 0.5) * width,
+            rect_data_max.top() + height * (value_max - price) / value_range));
     }
-    pub fn message_handler(&mut self, msg: Message) {
-        match msg {
-            Message::GotTradingHistory((symbol, data, error)) => {
-                if symbol == self.stock.symbol {
-                    self.data = data.into_iter().map(|x| x.into()).collect();
-                    self.requesting = false;
-                    self.error = error;
+    /// Fetch bars since the last one shown, keeping the chart while loading.
+    pub fn refresh_history(&mut self) {
+        let since = self.data.last().map(|x| x.date.to_string()).unwrap_or_default();
+        self.requesting = true;
+        self.error.clear();
+        self.call_uncached(ViewRequest::TradingHistory((self.stock.symbol.to_string(), self.typ, since)));
+    }
+    pub fn data_age_ui(&self, ui: &mut Ui, kind: ViewRequestKind) {
+        data_age_ui(ui, self.fetched_at.get(&kind).copied());
+    }
+    pub fn response_handler(&mut self, request: RequestId, response: ViewResponse, fetched_at: u64) {
+        if self.latest_requests.get(&response.kind()) != Some(&request) {
+            info!("[{}] drop stale response #{} of {:?}", self.stock.code, request, response.kind());
+            return;
+        }
+        if response.error().is_empty() && fetched_at > 0 {
+            self.fetched_at.insert(response.kind(), fetched_at);
+        }
+        match response {
+            ViewResponse::TradingHistory((since, data, error)) => {
+                let data: Vec<TradingHistoryValueItem> = data.into_iter().map(|x| x.into()).collect();
+                if since.is_empty() {
+                    self.data = data;
+                } else {
+                    merge_history(&mut self.data, data, |x| x.date.as_str());
                 }
+                self.requesting = false;
+                self.error = error;
             }
-            Message::GotPredicts((symbol, data, error)) => {
-                if symbol == self.stock.symbol {
-                    info!("{} set predicts", symbol);
-                    self.predicts = data;
-                    self.predicting = false;
-                    self.predict_error = error;
-                }
+            ViewResponse::Predicts((data, error)) => {
+                info!("{} set predicts", self.stock.symbol);
+                self.predicts = data;
+                self.predicting = false;
+                self.predict_error = error;
             }
-            Message::GotStockIssue((code, data, _error)) => {
-                if code == self.stock.code {
-                    info!("{} set issue", code);
+            ViewResponse::StockIssue((data, error)) => {
+                info!("{} set issue", self.stock.code);
+                self.requesting_issue = false;
+                if error.is_empty() {
+                    self.issue_detail = Some((&data).into());
                     self.issue = Some(data);
-                    self.requesting_issue = false;
                 }
+                self.issue_error = error;
             }
-            Message::GotGuideLine((code, data, error)) => {
-                if code == self.stock.code {
-                    info!("{} set guide line", code);
+            ViewResponse::GuideLine((data, error)) => {
+                info!("{} set guide line", self.stock.code);
+                if let Some(year) = self.share_index_pending.take() {
+                    self.share_index_years.insert(year);
+                } else if error.is_empty() {
+                    self.share_index_years.insert(self.guide_line_year as i32);
+                }
+                if error.is_empty() {
+                    merge_share_index(&mut self.share_index, data.share_index.clone());
+                    self.valuation_cache = Default::default();
+                }
+                if self.requesting_guide_line {
                     self.guide_line = Some(data);
                     self.requesting_guide_line = false;
                     self.guide_line_error = error;
                 }
             }
-            Message::GotIncomeAnalysis((code, data)) => {
-                if code == self.stock.code {
-                    info!("{} set income_analysis", code);
+            ViewResponse::IncomeAnalysis((data, error)) => {
+                info!("{} set income_analysis", self.stock.code);
+                if error.is_empty() {
                     self.income_analysis = Some(data);
-                    self.requesting_income_analysis = false;
                 }
+                self.requesting_income_analysis = false;
+                self.income_analysis_error = error;
+            }
+            ViewResponse::IncomeStatement((mut data, error)) => {
+                info!("{} set income statement", self.stock.code);
+                sort_periods(&mut data.data);
+                self.income_statement = Some(data);
+                self.add_statement_error(error);
             }
-            _ => {}
+            ViewResponse::BalanceSheet((mut data, error)) => {
+                info!("{} set balance sheet", self.stock.code);
+                sort_periods(&mut data.data);
+                self.balance_sheet = Some(data);
+                self.add_statement_error(error);
+            }
+            ViewResponse::CashFlowStatement((mut data, error)) => {
+                info!("{} set cash flow statement", self.stock.code);
+                sort_periods(&mut data.data);
+                self.cash_flow_statement = Some(data);
+                self.add_statement_error(error);
+                // the last one of statement requests
+                self.requesting_statements = false;
+            }
+        }
+    }
+    /// Statements come in three responses, each may fail, often for the same reason.
+    fn add_statement_error(&mut self, error: String) {
+        if error.is_empty() || self.statement_error.split("; ").any(|x| x == error) {
+            return;
+        }
+        if !self.statement_error.is_empty() {
+            self.statement_error.push_str("; ");
         }
+        self.statement_error.push_str(&error);
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test {
+    use rpc::api::{StockIssueResp, StockOverviewRequest, StockResp, TradingHistoryItem, TradingHistoryType};
+    use crate::message::ViewResponse;
+    use crate::service::ViewRequest;
+    use crate::stock_view::{merge_history, StockView};
+
+    #[test]
+    fn stale_response_dropped() {
+        let mut view = StockView::new(1, StockResp::default(), None);
+        view.call(ViewRequest::TradingHistory(("".to_string(), TradingHistoryType::Week, "".to_string())));
+        view.call(ViewRequest::TradingHistory(("".to_string(), TradingHistoryType::Daily, "".to_string())));
+        view.response_handler(1, ViewResponse::TradingHistory(("".to_string(), vec![TradingHistoryItem::default()], "".to_string())), 1);
+        assert!(view.data.is_empty());
+        view.response_handler(2, ViewResponse::TradingHistory(("".to_string(), vec![TradingHistoryItem::default()], "".to_string())), 2);
+        assert_eq!(view.data.len(), 1);
+    }
+
+    #[test]
+    fn overview_answers_parts() {
+        let mut view = StockView::new(1, StockResp::default(), None);
+        view.call(ViewRequest::Overview(StockOverviewRequest { issue: true, ..Default::default() }));
+        view.response_handler(1, ViewResponse::TradingHistory(("".to_string(), vec![TradingHistoryItem::default()], "".to_string())), 1);
+        view.response_handler(1, ViewResponse::StockIssue((StockIssueResp::default(), "".to_string())), 1);
+        assert_eq!(view.data.len(), 1);
+        assert!(view.issue.is_some());
+        // not asked for, the overview doesn't supersede it
+        view.response_handler(1, ViewResponse::IncomeAnalysis(Default::default()), 1);
+        assert!(view.income_analysis.is_none());
+    }
+
+    #[test]
+    fn income_analysis_error() {
+        let mut view = StockView::new(1, StockResp::default(), None);
+        view.request_income_analysis();
+        view.response_handler(1, ViewResponse::IncomeAnalysis((Default::default(), "timeout".to_string())), 0);
+        assert!(view.income_analysis.is_none() && !view.requesting_income_analysis);
+        assert_eq!(view.income_analysis_error, "timeout");
+        // the error is cleared by asking again
+        view.request_income_analysis();
+        assert!(view.income_analysis_error.is_empty());
+        view.response_handler(2, ViewResponse::IncomeAnalysis((Default::default(), "".to_string())), 1);
+        assert!(view.income_analysis.is_some());
+    }
+
+    #[test]
+    fn statement_errors_joined() {
+        let mut view = StockView::new(1, StockResp::default(), None);
+        view.call(ViewRequest::Statements("".to_string()));
+        view.response_handler(1, ViewResponse::IncomeStatement((Default::default(), "timeout".to_string())), 1);
+        view.response_handler(1, ViewResponse::BalanceSheet((Default::default(), "".to_string())), 1);
+        view.response_handler(1, ViewResponse::CashFlowStatement((Default::default(), "not found".to_string())), 1);
+        assert_eq!(view.statement_error, "timeout; not found");
+        view.request_statements(true);
+        view.response_handler(2, ViewResponse::IncomeStatement((Default::default(), "unavailable".to_string())), 2);
+        view.response_handler(2, ViewResponse::BalanceSheet((Default::default(), "unavailable".to_string())), 2);
+        view.response_handler(2, ViewResponse::CashFlowStatement((Default::default(), "unavailable".to_string())), 2);
+        assert_eq!(view.statement_error, "unavailable");
+    }
+
+    #[test]
+    fn merge_bars() {
+        let mut data = vec!["2023-01-01", "2023-01-02", "2023-01-03"];
+        merge_history(&mut data, vec!["2023-01-03", "2023-01-04"], |x| *x);
+        assert_eq!(data, vec!["2023-01-01", "2023-01-02", "2023-01-03", "2023-01-04"]);
+        merge_history(&mut data, vec![], |x| *x);
+        assert_eq!(data.len(), 4);
+    }
+}
@@ -36,9 +36,65 @@ pub fn block_on<F: Future<Output=()> + 'static>(f: F) {
     wasm_bindgen_futures::spawn_local(f);
 }
 
+/// Unix timestamp in seconds.
+pub fn now_seconds() -> u64 {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|x| x.as_secs())
+            .unwrap_or(0)
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        (js_sys::Date::now() / 1000.0) as u64
+    }
+}
+
+/// Unix timestamp in milliseconds, for measuring short intervals.
+pub fn now_millis() -> f64 {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|x| x.as_secs_f64() * 1e3)
+            .unwrap_or(0.0)
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        js_sys::Date::now()
+    }
+}
+
+pub fn format_duration(seconds: u64) -> String {
+    let (days, hours, minutes) = (seconds / 86400, seconds % 86400 / 3600, seconds % 3600 / 60);
+    if days > 0 {
+        format!("{}天{}小时", days, hours)
+    } else if hours > 0 {
+        format!("{}小时{}分", hours, minutes)
+    } else {
+        format!("{}分{}秒", minutes, seconds % 60)
+    }
+}
+
+/// Days since 1970-01-01 of a `YYYY-MM-DD` date.
+pub fn parse_days(text: &str) -> Option<i64> {
+    let mut parts = text.splitn(3, '-');
+    let y: i64 = parts.next()?.parse().ok()?;
+    let m: i64 = parts.next()?.parse().ok()?;
+    let d: i64 = parts.next()?.parse().ok()?;
+    // days from civil, see http://howardhinnant.github.io/date_algorithms.html
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((m + 9) % 12) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Some(era * 146097 + doe - 719468)
+}
+
 pub async fn sleep_ms(mills: u64) {
     #[cfg(not(target_arch = "wasm32"))]
-    std::thread::sleep(std::time::Duration::from_millis(mills));
+    tokio::time::sleep(std::time::Duration::from_millis(mills)).await;
     #[cfg(target_arch = "wasm32")]
     {
         // #[wasm_bindgen]
@@ -98,4 +154,24 @@ pub fn get_random_u32() -> u32 {
         Ok(r) => r,
         Err(_) => [0; 4],
     })
-}
\ No newline at end of file
+}
+/// Parse a numeric field which is sent as text by the server, `None` for empty or invalid values.
+pub fn parse_value(text: &str) -> Option<f64> {
+    let text = text.trim().trim_end_matches('%').replace(',', "");
+    if text.is_empty() {
+        return None;
+    }
+    text.parse::<f64>().ok().filter(|x| x.is_finite())
+}
+
+/// Format an amount in yuan with chinese units.
+pub fn format_amount(value: f64) -> String {
+    let abs = value.abs();
+    if abs >= 1e8 {
+        format!("{:.2}亿", value / 1e8)
+    } else if abs >= 1e4 {
+        format!("{:.2}万", value / 1e4)
+    } else {
+        format!("{:.2}", value)
+    }
+}
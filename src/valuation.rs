@@ -0,0 +1,447 @@
+use egui::{Color32, ComboBox, Grid, Painter, Rect, RichText, Stroke, Ui};
+use rpc::api::{IncomeStatementItem, ShareIndex};
+use crate::constants::VALUATION_YEARS;
+use crate::statements::previous_year_date;
+use crate::message::ViewRequestKind;
+use crate::server_info::{FEATURE_GUIDE_LINE, FEATURE_STATEMENTS};
+use crate::service::ViewRequest;
+use crate::stock_view::StockView;
+use crate::utils::{format_amount, parse_value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValuationBand {
+    None,
+    Pe,
+    Pb,
+}
+
+/// Percentiles drawn as bands on the chart.
+pub const BAND_PERCENTILES: &[(f64, Color32)] = &[
+    (0.1, Color32::from_rgb(0x4c, 0xaf, 0x50)),
+    (0.5, Color32::from_rgb(0xff, 0xc1, 0x07)),
+    (0.9, Color32::from_rgb(0xf4, 0x43, 0x36)),
+];
+
+/// Per-share figures of one reporting period.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PerShare {
+    pub date: String,
+    pub eps_ttm: Option<f64>,
+    pub bps: Option<f64>,
+    /// capital reserve per share
+    pub crps: Option<f64>,
+    /// total shares
+    pub shares: Option<f64>,
+    /// dividend per share of trailing twelve months
+    pub dps_ttm: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Valuation {
+    pub date: String,
+    pub close: f64,
+    pub per_share: PerShare,
+    pub pe_ttm: Option<f64>,
+    pub pb: Option<f64>,
+    pub market_cap: Option<f64>,
+    pub revenue_ttm: Option<f64>,
+    pub ps_ttm: Option<f64>,
+    pub dividend_yield: Option<f64>,
+    /// percentile of current PE/PB in loaded trading history, 0..=1
+    pub pe_percentile: Option<f64>,
+    pub pb_percentile: Option<f64>,
+}
+
+impl Valuation {
+    /// Valuation at `close`, percentiles are left to the caller.
+    pub fn new(date: &str, close: f64, per_share: PerShare, revenue_ttm: Option<f64>) -> Self {
+        let market_cap = per_share.shares.filter(|x| *x > 0.0).map(|x| x * close);
+        Self {
+            date: date.to_string(),
+            close,
+            pe_ttm: ratio(close, per_share.eps_ttm),
+            pb: ratio(close, per_share.bps),
+            market_cap,
+            revenue_ttm,
+            ps_ttm: market_cap.and_then(|x| ratio(x, revenue_ttm)),
+            dividend_yield: per_share.dps_ttm.and_then(|x| ratio(x, Some(close))),
+            per_share,
+            pe_percentile: None,
+            pb_percentile: None,
+        }
+    }
+}
+
+/// Figures derived from trading history and share indexes, rebuilt when either changes.
+#[derive(Debug, Default)]
+pub struct ValuationCache {
+    /// (bars, first date, last date, last close) the cache was built from
+    key: Option<(usize, String, String, u32)>,
+    series: Vec<PerShare>,
+    /// (PE, PB) of every bar
+    multiples: Vec<(Option<f64>, Option<f64>)>,
+    /// finite multiples in history, ascending
+    pe_sorted: Vec<f64>,
+    pb_sorted: Vec<f64>,
+    bands: Option<(ValuationBand, Vec<(Color32, Vec<Option<f64>>)>)>,
+}
+
+/// Trailing twelve months value from cumulative year-to-date reports,
+/// `series` is (date, value) sorted newest first.
+pub fn ttm(series: &[(&str, Option<f64>)], index: usize) -> Option<f64> {
+    let (date, value) = series.get(index)?;
+    let value = (*value)?;
+    if date.ends_with("12-31") {
+        return Some(value);
+    }
+    let year: i32 = date.get(0..4)?.parse().ok()?;
+    let last_annual = format!("{}-12-31", year - 1);
+    let last_same = previous_year_date(date)?;
+    let find = |d: &str| series.iter().find(|x| x.0 == d).and_then(|x| x.1);
+    Some(value + find(&last_annual)? - find(&last_same)?)
+}
+
+/// Share of `values` not greater than `current`.
+pub fn percentile_rank(values: &[f64], current: f64) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    Some(values.iter().filter(|x| **x <= current).count() as f64 / values.len() as f64)
+}
+
+/// `sorted` must be sorted ascending.
+pub fn quantile(sorted: &[f64], q: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let index = ((sorted.len() - 1) as f64 * q).round() as usize;
+    sorted.get(index).copied()
+}
+
+/// Latest reporting period not later than `date`, `series` sorted newest first.
+pub fn per_share_at<'a>(series: &'a [PerShare], date: &str) -> Option<&'a PerShare> {
+    series.iter().find(|x| x.date.as_str() <= date)
+}
+
+fn ratio(a: f64, b: Option<f64>) -> Option<f64> {
+    b.filter(|b| *b > 0.0).map(|b| a / b).filter(|x| x.is_finite())
+}
+
+fn sorted(values: impl Iterator<Item=Option<f64>>) -> Vec<f64> {
+    let mut values: Vec<f64> = values.flatten().filter(|x| x.is_finite()).collect();
+    values.sort_by(f64::total_cmp);
+    values
+}
+
+/// Per-share series of guide line share indexes, `share_index` sorted newest first.
+///
+/// EPS is cumulative within a year, falls back to diluted EPS when weighted average is missing.
+pub fn per_share_series(share_index: &[ShareIndex]) -> Vec<PerShare> {
+    let eps: Vec<_> = share_index.iter()
+        .map(|x| (x.date.as_str(), parse_value(&x.epswa).or_else(|| parse_value(&x.diluted_eps))))
+        .collect();
+    let dps: Vec<_> = share_index.iter().map(|x| (x.date.as_str(), parse_value(&x.dps))).collect();
+    share_index.iter().enumerate().map(|(i, item)| PerShare {
+        date: item.date.to_string(),
+        eps_ttm: ttm(&eps, i),
+        bps: parse_value(&item.bps),
+        crps: parse_value(&item.crps),
+        shares: parse_value(&item.total_shares),
+        dps_ttm: ttm(&dps, i),
+    }).collect()
+}
+
+/// Trailing twelve months revenue of the latest income statement, `income` sorted newest first.
+pub fn revenue_ttm(income: &[IncomeStatementItem]) -> Option<f64> {
+    let revenue: Vec<_> = income.iter().map(|x| (x.date.as_str(), parse_value(&x.revenue))).collect();
+    ttm(&revenue, 0)
+}
+
+/// Add share indexes of a guide line, keeping `share_index` newest first without duplicated periods.
+pub fn merge_share_index(share_index: &mut Vec<ShareIndex>, data: Vec<ShareIndex>) {
+    for item in data {
+        share_index.retain(|x| x.date != item.date);
+        share_index.push(item);
+    }
+    share_index.sort_by(|a, b| b.date.cmp(&a.date));
+}
+
+impl StockView {
+    /// Fetch guide lines of the years covered by loaded trading history, one year at a time.
+    pub fn request_share_index(&mut self) {
+        if self.requesting_guide_line || self.share_index_pending.is_some()
+            || !self.server_info.supports(FEATURE_GUIDE_LINE) {
+            return;
+        }
+        let year = |x: Option<&String>| x.and_then(|x| x.get(0..4)?.parse::<i32>().ok());
+        let (Some(first), Some(last)) = (year(self.data.first().map(|x| &x.date)), year(self.data.last().map(|x| &x.date))) else {
+            return;
+        };
+        // TTM of the first year needs the year before
+        let first = first.max(last - VALUATION_YEARS + 1) - 1;
+        if let Some(year) = (first..=last).rev().find(|x| !self.share_index_years.contains(x)) {
+            self.share_index_pending = Some(year);
+            self.call(ViewRequest::GuideLine((self.stock.code.to_string(), year.to_string())));
+        }
+    }
+    fn update_valuation_cache(&mut self) {
+        let key = match (self.data.first(), self.data.last()) {
+            (Some(first), Some(last)) => Some((self.data.len(), first.date.to_string(), last.date.to_string(), last.close.to_bits())),
+            _ => None,
+        };
+        if key.is_some() && self.valuation_cache.key == key {
+            return;
+        }
+        let series = per_share_series(&self.share_index);
+        let multiples: Vec<_> = self.data.iter().map(|item| {
+            match per_share_at(&series, &item.date) {
+                Some(p) if item.close > 0.0 => (ratio(item.close as f64, p.eps_ttm), ratio(item.close as f64, p.bps)),
+                _ => (None, None),
+            }
+        }).collect();
+        self.valuation_cache = ValuationCache {
+            key,
+            pe_sorted: sorted(multiples.iter().map(|x| x.0)),
+            pb_sorted: sorted(multiples.iter().map(|x| x.1)),
+            series,
+            multiples,
+            bands: None,
+        };
+    }
+    pub fn valuation(&mut self) -> Option<Valuation> {
+        self.update_valuation_cache();
+        let last = self.data.iter().rev().find(|x| x.valid())?;
+        let cache = &self.valuation_cache;
+        let per_share = cache.series.first()?.clone();
+        let revenue = self.income_statement.as_ref().and_then(|x| revenue_ttm(&x.data));
+        let mut valuation = Valuation::new(&last.date, last.close as f64, per_share, revenue);
+        valuation.pe_percentile = valuation.pe_ttm.and_then(|x| percentile_rank(&cache.pe_sorted, x));
+        valuation.pb_percentile = valuation.pb.and_then(|x| percentile_rank(&cache.pb_sorted, x));
+        Some(valuation)
+    }
+    /// Price of each percentile band for every trading history item.
+    pub fn valuation_bands(&mut self) -> &[(Color32, Vec<Option<f64>>)] {
+        self.update_valuation_cache();
+        let band = self.valuation_band;
+        let cache = &mut self.valuation_cache;
+        if cache.bands.as_ref().map(|x| x.0) != Some(band) {
+            let bands = match band {
+                ValuationBand::None => vec![],
+                _ => {
+                    let history = if band == ValuationBand::Pe { &cache.pe_sorted } else { &cache.pb_sorted };
+                    BAND_PERCENTILES.iter().filter_map(|(q, color)| {
+                        let multiple = quantile(history, *q)?;
+                        let prices = self.data.iter().map(|item| {
+                            let p = per_share_at(&cache.series, &item.date)?;
+                            let base = if band == ValuationBand::Pe { p.eps_ttm } else { p.bps };
+                            base.filter(|x| *x > 0.0).map(|x| x * multiple)
+                        }).collect();
+                        Some((*color, prices))
+                    }).collect()
+                }
+            };
+            cache.bands = Some((band, bands));
+        }
+        cache.bands.as_ref().map(|x| x.1.as_slice()).unwrap_or_default()
+    }
+    /// Draw valuation bands over the chart, `to_pos` maps (index, price) to screen.
+    pub fn paint_valuation_bands(&mut self, painter: &Painter, clip: Rect, to_pos: impl Fn(usize, f32) -> egui::Pos2) {
+        if self.valuation_band == ValuationBand::None {
+            return;
+        }
+        self.request_share_index();
+        let painter = painter.with_clip_rect(clip);
+        for (color, prices) in self.valuation_bands() {
+            let mut last = None;
+            for (i, price) in prices.iter().enumerate() {
+                let pos = price.map(|x| to_pos(i, x as f32));
+                if let (Some(a), Some(b)) = (last, pos) {
+                    painter.line_segment([a, b], Stroke::new(1.0, *color));
+                }
+                last = pos;
+            }
+        }
+    }
+    pub fn valuation_ui(&mut self, ui: &mut Ui) {
+        self.request_share_index();
+        // revenue for PS
+        if self.server_info.supports(FEATURE_STATEMENTS) && !self.requesting_statements && self.income_statement.is_none() {
+            self.request_statements(true);
+        }
+        ui.horizontal(|ui| {
+            ComboBox::new(format!("{}-valuation-band", self.stock.symbol), "K 线估值带")
+                .selected_text(match self.valuation_band {
+                    ValuationBand::None => "不显示",
+                    ValuationBand::Pe => "PE(TTM)",
+                    ValuationBand::Pb => "PB",
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.valuation_band, ValuationBand::None, "不显示");
+                    ui.selectable_value(&mut self.valuation_band, ValuationBand::Pe, "PE(TTM)");
+                    ui.selectable_value(&mut self.valuation_band, ValuationBand::Pb, "PB");
+                });
+            if self.valuation_band != ValuationBand::None {
+                for (q, color) in BAND_PERCENTILES {
+                    ui.label(RichText::new(format!("{:.0}%分位", q * 100.0)).color(*color));
+                }
+            }
+            if self.share_index_pending.is_some() {
+                ui.spinner();
+            }
+            self.data_age_ui(ui, ViewRequestKind::GuideLine);
+        });
+        let valuation = match self.valuation() {
+            Some(v) => v,
+            None => {
+                ui.horizontal(|ui| {
+                    if self.share_index_pending.is_some() || self.requesting {
+                        ui.spinner();
+                        ui.label("正在加载估值数据...");
+                    } else {
+                        ui.label("缺少行情或财务指标数据，无法计算估值");
+                    }
+                });
+                return;
+            }
+        };
+        let text = |x: Option<f64>| x.map(|x| format!("{:.2}", x)).unwrap_or_else(|| "--".to_string());
+        let percentile = |x: Option<f64>| x.map(|x| format!("{:.1}%", x * 100.0)).unwrap_or_else(|| "--".to_string());
+        Grid::new(format!("{}-valuation-grid", self.stock.symbol))
+            .num_columns(3)
+            .spacing([40.0, 4.0])
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong("指标");
+                ui.strong("数值");
+                ui.strong("历史分位");
+                ui.end_row();
+                ui.label("最新收盘价");
+                ui.label(format!("{:.2} ({})", valuation.close, valuation.date));
+                ui.label("");
+                ui.end_row();
+                ui.label("市盈率 PE(TTM)");
+                ui.label(match valuation.per_share.eps_ttm {
+                    Some(eps) if eps <= 0.0 => "亏损".to_string(),
+                    _ => text(valuation.pe_ttm),
+                });
+                ui.label(percentile(valuation.pe_percentile));
+                ui.end_row();
+                ui.label("市净率 PB");
+                ui.label(text(valuation.pb));
+                ui.label(percentile(valuation.pb_percentile));
+                ui.end_row();
+                ui.label("市销率 PS(TTM)");
+                ui.label(text(valuation.ps_ttm));
+                ui.label("");
+                ui.end_row();
+                ui.label("股息率(TTM)");
+                ui.label(percentile(valuation.dividend_yield));
+                ui.label("");
+                ui.end_row();
+                ui.label("总市值");
+                ui.label(valuation.market_cap.map(format_amount).unwrap_or_else(|| "--".to_string()));
+                ui.label("");
+                ui.end_row();
+                ui.label("营业收入(TTM)");
+                ui.label(valuation.revenue_ttm.map(format_amount).unwrap_or_else(|| "--".to_string()));
+                ui.label("");
+                ui.end_row();
+                ui.label(format!("每股收益(TTM, {})", valuation.per_share.date));
+                ui.label(text(valuation.per_share.eps_ttm));
+                ui.label("");
+                ui.end_row();
+                ui.label("每股净资产");
+                ui.label(text(valuation.per_share.bps));
+                ui.label("");
+                ui.end_row();
+                ui.label("每股资本公积金");
+                ui.label(text(valuation.per_share.crps));
+                ui.label("");
+                ui.end_row();
+                ui.label("每股股利(TTM)");
+                ui.label(text(valuation.per_share.dps_ttm));
+                ui.label("");
+                ui.end_row();
+                ui.label("总股本");
+                ui.label(valuation.per_share.shares.map(format_amount).unwrap_or_else(|| "--".to_string()));
+                ui.label("");
+                ui.end_row();
+            });
+        ui.label(RichText::new("每股指标和总股本取自财务指标，营业收入取自利润表，历史分位基于已加载的 K 线").color(ui.visuals().weak_text_color()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rpc::api::{IncomeStatementItem, ShareIndex};
+    use crate::valuation::{merge_share_index, per_share_series, percentile_rank, quantile, revenue_ttm, ttm, PerShare, Valuation};
+
+    #[test]
+    fn valuation_ttm() {
+        let series = [
+            ("2022-09-30", Some(3.0)),
+            ("2022-06-30", Some(2.0)),
+            ("2021-12-31", Some(4.0)),
+            ("2021-09-30", Some(2.5)),
+        ];
+        assert_eq!(ttm(&series, 0), Some(4.5));
+        assert_eq!(ttm(&series, 1), None);
+        assert_eq!(ttm(&series, 2), Some(4.0));
+        assert_eq!(percentile_rank(&[1.0, 2.0, 3.0, 4.0], 2.0), Some(0.5));
+        assert_eq!(quantile(&[1.0, 2.0, 3.0], 0.5), Some(2.0));
+    }
+
+    #[test]
+    fn share_index_series() {
+        let index = |date: &str, epswa: &str, diluted_eps: &str, bps: &str| ShareIndex {
+            date: date.to_string(),
+            epswa: epswa.to_string(),
+            diluted_eps: diluted_eps.to_string(),
+            bps: bps.to_string(),
+            crps: "0.5".to_string(),
+            total_shares: "1000".to_string(),
+            dps: if date.ends_with("12-31") { "1.2" } else { "0" }.to_string(),
+            ..Default::default()
+        };
+        let mut share_index = vec![];
+        merge_share_index(&mut share_index, vec![index("2021-09-30", "2.5", "", "9"), index("2021-12-31", "4", "", "10")]);
+        merge_share_index(&mut share_index, vec![index("2022-09-30", "", "3", "11"), index("2021-12-31", "4", "", "10")]);
+        let dates: Vec<_> = share_index.iter().map(|x| x.date.as_str()).collect();
+        assert_eq!(dates, vec!["2022-09-30", "2021-12-31", "2021-09-30"]);
+        let series = per_share_series(&share_index);
+        assert_eq!(series[0].eps_ttm, Some(4.5));
+        assert_eq!(series[0].bps, Some(11.0));
+        assert_eq!(series[0].crps, Some(0.5));
+        assert_eq!(series[0].shares, Some(1000.0));
+        // dividend of the last annual report
+        assert_eq!(series[0].dps_ttm, Some(1.2));
+        assert_eq!(series[1].eps_ttm, Some(4.0));
+        assert_eq!(series[2].eps_ttm, None);
+    }
+
+    #[test]
+    fn market_valuation() {
+        let income = |date: &str, revenue: &str| IncomeStatementItem { date: date.to_string(), revenue: revenue.to_string(), ..Default::default() };
+        let income = [income("2022-06-30", "300"), income("2021-12-31", "500"), income("2021-06-30", "200")];
+        assert_eq!(revenue_ttm(&income), Some(600.0));
+        let per_share = PerShare {
+            date: "2022-06-30".to_string(),
+            eps_ttm: Some(0.5),
+            bps: Some(4.0),
+            shares: Some(100.0),
+            dps_ttm: Some(0.2),
+            ..Default::default()
+        };
+        let v = Valuation::new("2022-07-01", 10.0, per_share.clone(), revenue_ttm(&income));
+        assert_eq!(v.market_cap, Some(1000.0));
+        assert_eq!(v.ps_ttm, Some(1000.0 / 600.0));
+        assert_eq!(v.dividend_yield, Some(0.2 / 10.0));
+        assert_eq!(v.pe_ttm, Some(20.0));
+        assert_eq!(v.pb, Some(2.5));
+        // nothing to divide by
+        let v = Valuation::new("2022-07-01", 10.0, PerShare { shares: None, dps_ttm: None, ..per_share.clone() }, Some(600.0));
+        assert_eq!((v.market_cap, v.ps_ttm, v.dividend_yield), (None, None, None));
+        let v = Valuation::new("2022-07-01", 10.0, per_share, Some(0.0));
+        assert_eq!((v.market_cap, v.ps_ttm), (Some(1000.0), None));
+    }
+}